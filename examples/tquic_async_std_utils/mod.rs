@@ -1,110 +0,0 @@
-// Copyright (c) 2023 The TQUIC Authors.
-//
-// Licensed under the Apache License, Version 2.0 (the "License");
-// you may not use this file except in compliance with the License.
-// You may obtain a copy of the License at
-//
-//     http://www.apache.org/licenses/LICENSE-2.0
-//
-// Unless required by applicable law or agreed to in writing, software
-// distributed under the License is distributed on an "AS IS" BASIS,
-// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
-// See the License for the specific language governing permissions and
-// limitations under the License.
-
-use std::net::IpAddr;
-use std::net::Ipv4Addr;
-use std::net::Ipv6Addr;
-use std::net::SocketAddr;
-use std::time::Duration;
-
-use async_io::Async;
-use log::debug;
-
-use std::net::UdpSocket;
-use tquic::PacketInfo;
-use tquic::PacketSendHandler;
-
-pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
-
-/// UDP socket wrapper for QUIC
-pub struct QuicSocket {
-    /// The underlying UDP sockets for QUIC Endpoint.
-    pub(crate) socket: Async<UdpSocket>,
-
-    raw: UdpSocket,
-
-    /// Local address of the initial socket.
-    local_addr: SocketAddr,
-}
-
-impl QuicSocket {
-    pub async fn new(local: &SocketAddr) -> Result<Self> {
-        let socket = UdpSocket::bind(*local)?;
-        socket.set_nonblocking(true);
-        let local_addr = socket.local_addr()?;
-
-        Ok(Self {
-            raw: socket.try_clone().unwrap(),
-            socket: Async::new(socket).expect(""),
-            local_addr,
-        })
-    }
-
-    pub async fn new_client_socket(is_ipv4: bool) -> Result<Self> {
-        let local = match is_ipv4 {
-            true => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
-            false => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
-        };
-        QuicSocket::new(&SocketAddr::new(local, 0)).await
-    }
-
-    /// Return the local address of the initial socket.
-    pub fn local_addr(&self) -> SocketAddr {
-        self.local_addr
-    }
-
-    pub async fn wait_data(&self, timeout: Option<Duration>) -> std::io::Result<()> {
-        if let Some(timeout) = timeout {
-            async_std::io::timeout(timeout, self.socket.readable()).await?
-        } else {
-            self.socket.readable().await?;
-        }
-        Ok(())
-    }
-
-    /// Receive data from the socket.
-    pub fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr, SocketAddr)> {
-        match self.raw.recv_from(buf) {
-            Ok((len, remote)) => Ok((len, self.raw.local_addr()?, remote)),
-            Err(e) => Err(e),
-        }
-    }
-
-    /// Send data on the socket to the given address.
-    /// Note: packets with unknown src address are dropped.
-    pub fn send_to(&self, buf: &[u8], src: SocketAddr, dst: SocketAddr) -> std::io::Result<usize> {
-        self.raw.send_to(buf, dst)
-    }
-}
-
-impl PacketSendHandler for QuicSocket {
-    fn on_packets_send(&self, pkts: &[(Vec<u8>, PacketInfo)]) -> tquic::Result<usize> {
-        let mut count = 0;
-        for (pkt, info) in pkts {
-            if let Err(e) = self.send_to(pkt, info.src, info.dst) {
-                if e.kind() == std::io::ErrorKind::WouldBlock {
-                    debug!("socket send would block");
-                    return Ok(count);
-                }
-                return Err(tquic::Error::InvalidOperation(format!(
-                    "socket send_to(): {:?}",
-                    e
-                )));
-            }
-            debug!("written {} bytes", pkt.len());
-            count += 1;
-        }
-        Ok(count)
-    }
-}
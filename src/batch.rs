@@ -0,0 +1,374 @@
+//! Several datagrams per syscall, with sendmmsg and recvmmsg on Linux.
+
+use std::io;
+use std::net::SocketAddr;
+#[cfg(target_os = "linux")]
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use std::os::fd::RawFd;
+
+/// Datagrams the workloads hand to a transport at once.
+pub const BATCH_SIZE: usize = 64;
+
+/// Buffers for up to `capacity` datagrams, to send or as received.
+pub struct Batch {
+    bufs: Vec<Vec<u8>>,
+    lens: Vec<usize>,
+    sources: Vec<Option<SocketAddr>>,
+    len: usize,
+}
+
+impl Batch {
+    /// Room for `capacity` datagrams of up to `size` bytes each.
+    pub fn new(capacity: usize, size: usize) -> Self {
+        assert!(capacity > 0, "empty batch");
+        Self {
+            bufs: vec![vec![0; size]; capacity],
+            lens: vec![0; capacity],
+            sources: vec![None; capacity],
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bufs.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Append a datagram of `len` bytes, to be written into.
+    pub fn push(&mut self, len: usize) -> &mut [u8] {
+        assert!(!self.is_full(), "batch is full");
+        let i = self.len;
+        self.len += 1;
+        self.lens[i] = len;
+        self.sources[i] = None;
+        &mut self.bufs[i][..len]
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &[u8]> {
+        (0..self.len).map(|i| &self.bufs[i][..self.lens[i]])
+    }
+
+    /// The `i`th datagram, to be written into.
+    pub fn get_mut(&mut self, i: usize) -> &mut [u8] {
+        assert!(i < self.len, "index out of the batch");
+        &mut self.bufs[i][..self.lens[i]]
+    }
+
+    /// Where the `i`th received datagram came from.
+    pub fn source(&self, i: usize) -> Option<SocketAddr> {
+        self.sources[..self.len][i]
+    }
+
+    /// Buffer the next datagram is received into.
+    pub fn spare(&mut self) -> &mut [u8] {
+        &mut self.bufs[self.len]
+    }
+
+    /// Account a datagram received into [`Batch::spare`].
+    pub fn filled(&mut self, len: usize, source: Option<SocketAddr>) {
+        self.lens[self.len] = len;
+        self.sources[self.len] = source;
+        self.len += 1;
+    }
+}
+
+/// Send `pkts` with as few sendmmsg calls as it takes, returning how many
+/// went out before the socket would block. `WouldBlock` if none did.
+#[cfg(target_os = "linux")]
+pub(crate) fn send_mmsg<'a>(
+    fd: RawFd,
+    pkts: impl ExactSizeIterator<Item = (&'a [u8], SocketAddr)>,
+) -> io::Result<usize> {
+    let mut addrs = Vec::with_capacity(pkts.len());
+    let mut iovs = Vec::with_capacity(pkts.len());
+    for (buf, dst) in pkts {
+        addrs.push(sockaddr(&dst));
+        iovs.push(libc::iovec {
+            iov_base: buf.as_ptr() as *mut _,
+            iov_len: buf.len(),
+        });
+    }
+    // SAFETY: zeroed mmsghdrs are valid, the pointers set below outlive the
+    // calls.
+    let mut msgs: Vec<libc::mmsghdr> = vec![unsafe { std::mem::zeroed() }; iovs.len()];
+    for ((msg, iov), (addr, addr_len)) in msgs.iter_mut().zip(&mut iovs).zip(&mut addrs) {
+        msg.msg_hdr.msg_name = addr as *mut _ as *mut _;
+        msg.msg_hdr.msg_namelen = *addr_len;
+        msg.msg_hdr.msg_iov = iov;
+        msg.msg_hdr.msg_iovlen = 1;
+    }
+
+    let mut sent = 0;
+    while sent < msgs.len() {
+        let rest = &mut msgs[sent..];
+        // SAFETY: `rest` points to initialized headers whose buffers live
+        // until this function returns.
+        let n = unsafe { libc::sendmmsg(fd, rest.as_mut_ptr(), rest.len() as _, 0) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            match e.kind() {
+                io::ErrorKind::Interrupted => continue,
+                io::ErrorKind::WouldBlock if sent > 0 => break,
+                _ => return Err(e),
+            }
+        }
+        sent += n as usize;
+    }
+    Ok(sent)
+}
+
+/// Receive up to `max` queued datagrams into `batch` with one recvmmsg
+/// call, replacing what it held. Waits for the first one on blocking sockets.
+#[cfg(target_os = "linux")]
+pub(crate) fn recv_mmsg(fd: RawFd, batch: &mut Batch, max: usize) -> io::Result<()> {
+    batch.clear();
+    let capacity = batch.capacity().min(max);
+    // SAFETY: all zeroes is a valid sockaddr_storage.
+    let mut addrs: Vec<libc::sockaddr_storage> = vec![unsafe { std::mem::zeroed() }; capacity];
+    let mut iovs: Vec<libc::iovec> = batch.bufs[..capacity]
+        .iter_mut()
+        .map(|buf| libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut _,
+            iov_len: buf.len(),
+        })
+        .collect();
+    // SAFETY: as in `send_mmsg`.
+    let mut msgs: Vec<libc::mmsghdr> = vec![unsafe { std::mem::zeroed() }; capacity];
+    for ((msg, iov), addr) in msgs.iter_mut().zip(&mut iovs).zip(&mut addrs) {
+        msg.msg_hdr.msg_name = addr as *mut _ as *mut _;
+        msg.msg_hdr.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as _;
+        msg.msg_hdr.msg_iov = iov;
+        msg.msg_hdr.msg_iovlen = 1;
+    }
+
+    let n = loop {
+        // SAFETY: the headers point into `batch`, `iovs` and `addrs`, all
+        // alive and large enough for what they describe.
+        let n = unsafe {
+            libc::recvmmsg(
+                fd,
+                msgs.as_mut_ptr(),
+                capacity as _,
+                libc::MSG_WAITFORONE,
+                std::ptr::null_mut(),
+            )
+        };
+        if n >= 0 {
+            break n as usize;
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    };
+    for (i, msg) in msgs[..n].iter().enumerate() {
+        batch.lens[i] = msg.msg_len as usize;
+        batch.sources[i] = socket_addr(&addrs[i]);
+    }
+    batch.len = n;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn send_mmsg<'a>(
+    _fd: RawFd,
+    _pkts: impl ExactSizeIterator<Item = (&'a [u8], SocketAddr)>,
+) -> io::Result<usize> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn recv_mmsg(_fd: RawFd, _batch: &mut Batch, _max: usize) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    // SAFETY: all zeroes is a valid sockaddr_storage.
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            // SAFETY: sockaddr_storage is large and aligned enough for any
+            // address family.
+            let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            sin.sin_family = libc::AF_INET as _;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+            std::mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            // SAFETY: as above.
+            let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+            sin6.sin6_family = libc::AF_INET6 as _;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_addr.s6_addr = addr.ip().octets();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_scope_id = addr.scope_id();
+            std::mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as _)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn socket_addr(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+    match storage.ss_family as libc::c_int {
+        libc::AF_INET => {
+            // SAFETY: the family says what the storage holds.
+            let sin = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+            Some(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)),
+                u16::from_be(sin.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: as above.
+            let sin6 = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id,
+            )))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::os::fd::AsRawFd;
+
+    use super::*;
+
+    #[test]
+    fn keeps_track_of_pushed_and_received_datagrams() {
+        let mut batch = Batch::new(3, 100);
+        assert!(batch.is_empty());
+        batch.push(10).fill(1);
+        batch.get_mut(0)[0] = 9;
+        let from = "127.0.0.1:4433".parse().unwrap();
+        batch.spare()[..20].fill(2);
+        batch.filled(20, Some(from));
+        assert_eq!(batch.spare().len(), 100);
+        batch.push(0);
+        assert!(batch.is_full());
+
+        let lens: Vec<_> = batch.iter().map(<[u8]>::len).collect();
+        assert_eq!(lens, [10, 20, 0]);
+        assert_eq!(batch.iter().next().unwrap()[..2], [9, 1]);
+        assert_eq!(batch.iter().nth(1).unwrap(), [2; 20]);
+        assert_eq!(
+            (0..3).map(|i| batch.source(i)).collect::<Vec<_>>(),
+            [None, Some(from), None]
+        );
+
+        batch.clear();
+        assert_eq!((batch.len(), batch.capacity()), (0, 3));
+        batch.push(5);
+        assert_eq!(batch.source(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "batch is full")]
+    fn refuses_to_overflow() {
+        let mut batch = Batch::new(1, 10);
+        batch.push(1);
+        batch.push(1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn converts_socket_addresses_back_and_forth() {
+        for addr in [
+            "127.0.0.1:4433".parse().unwrap(),
+            "0.0.0.0:0".parse().unwrap(),
+            "[::1]:443".parse().unwrap(),
+            SocketAddr::V6(SocketAddrV6::new(
+                "fe80::1".parse().unwrap(),
+                65535,
+                0x12345,
+                7,
+            )),
+        ] {
+            let (storage, len) = sockaddr(&addr);
+            let expected = match addr {
+                SocketAddr::V4(_) => std::mem::size_of::<libc::sockaddr_in>(),
+                SocketAddr::V6(_) => std::mem::size_of::<libc::sockaddr_in6>(),
+            };
+            assert_eq!(len as usize, expected);
+            assert_eq!(socket_addr(&storage), Some(addr));
+        }
+        // SAFETY: all zeroes is a valid sockaddr_storage.
+        let unknown: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        assert_eq!(socket_addr(&unknown), None);
+    }
+
+    /// Send a batch of differently sized datagrams from two sockets at
+    /// `ip`, then receive them with one recvmmsg.
+    #[cfg(target_os = "linux")]
+    fn mmsg_round_trip(ip: &str) {
+        let bind = || UdpSocket::bind((ip, 0));
+        let (Ok(rx), Ok(a), Ok(b)) = (bind(), bind(), bind()) else {
+            // No such loopback here.
+            return;
+        };
+        let dst = rx.local_addr().unwrap();
+        let pkts: Vec<_> = (0..6u8).map(|i| vec![i; 100 * i as usize + 1]).collect();
+        for (i, pkt) in pkts.iter().enumerate() {
+            let tx = if i % 2 == 0 { &a } else { &b };
+            let sent = send_mmsg(tx.as_raw_fd(), [(pkt.as_slice(), dst)].into_iter()).unwrap();
+            assert_eq!(sent, 1);
+        }
+        assert_eq!(
+            send_mmsg(a.as_raw_fd(), pkts.iter().map(|p| (p.as_slice(), dst))).unwrap(),
+            6
+        );
+
+        let mut batch = Batch::new(BATCH_SIZE, 1500);
+        let mut received = Vec::new();
+        while received.len() < 12 {
+            recv_mmsg(rx.as_raw_fd(), &mut batch, 4).unwrap();
+            assert!((1..=4).contains(&batch.len()));
+            for (i, pkt) in batch.iter().enumerate() {
+                received.push((pkt.to_vec(), batch.source(i).unwrap()));
+            }
+        }
+        let sources = [a.local_addr().unwrap(), b.local_addr().unwrap()];
+        for (i, (pkt, from)) in received.into_iter().enumerate() {
+            let expected = match i < 6 {
+                true => sources[i % 2],
+                false => sources[0],
+            };
+            assert_eq!((pkt, from), (pkts[i % 6].clone(), expected));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sends_and_receives_batches_over_ipv4() {
+        mmsg_round_trip("127.0.0.1");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sends_and_receives_batches_over_ipv6() {
+        mmsg_round_trip("::1");
+    }
+}
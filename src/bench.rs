@@ -0,0 +1,418 @@
+//! Command line entry points shared by the `bench` binary.
+
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use clap::{Args, ValueEnum};
+use log::error;
+
+use crate::driver::WorkloadOpt;
+use crate::meter::{Echoed, ServerMeter};
+use crate::quinn::{AsyncStdQuinn, MonoioQuinn, TokioQuinn};
+use crate::report::{ReportOpt, Reporter, Role};
+use crate::runtime::{join_all, Runtime};
+use crate::tcp::{AsyncStdTcp, MioTcp, MonoioTcp, NativeTcp, TokioTcp};
+use crate::tls::{AsyncStdTls, MonoioTls, NativeTls, TokioTls};
+use crate::tquic::{AsyncStdTquic, MioTquic, MonoioTquic, NativeTquic, TokioTquic};
+use crate::udp::{AsyncStdUdp, MioUdp, MonoioUdp, NativeUdp, TokioUdp};
+use crate::{driver, Crypto, Delivery, Listener, Meter, StreamKind, Transport, TransportOpt};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Proto {
+    Tcp,
+    /// TLS 1.3 over TCP.
+    Tls,
+    Udp,
+    Quinn,
+    Tquic,
+}
+
+impl Proto {
+    /// How the protocol's transports frame messages.
+    pub fn delivery(self) -> Delivery {
+        match self {
+            Proto::Tcp | Proto::Tls | Proto::Quinn | Proto::Tquic => Delivery::Stream,
+            Proto::Udp => Delivery::Datagram,
+        }
+    }
+}
+
+impl fmt::Display for Proto {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+/// Options understood by both sides.
+#[derive(Args, Debug, Clone)]
+pub struct BenchOpt {
+    /// Protocol to benchmark.
+    #[clap(long, value_enum)]
+    pub proto: Proto,
+
+    /// Runtime driving the sockets.
+    #[clap(long, value_enum, default_value_t = Runtime::Tokio)]
+    pub runtime: Runtime,
+
+    #[clap(flatten)]
+    pub transport: TransportOpt,
+
+    #[clap(flatten)]
+    pub report: ReportOpt,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ServerOpt {
+    /// Address to listen.
+    #[clap(short, long, default_value = "0.0.0.0:8080", value_name = "ADDR")]
+    pub listen: SocketAddr,
+
+    #[clap(flatten)]
+    pub bench: BenchOpt,
+}
+
+impl BenchOpt {
+    /// Command line for a server process sharing these options. Result
+    /// options are left out, each side picks its own.
+    pub(crate) fn server_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--proto".to_string(),
+            self.proto.to_string(),
+            "--runtime".to_string(),
+            self.runtime.to_string(),
+        ];
+        args.extend(self.transport.server_args());
+        args
+    }
+}
+
+/// What the client runs and for how long.
+#[derive(Args, Debug, Clone)]
+pub struct RunOpt {
+    /// Stop after this many seconds of measurement, run until killed otherwise.
+    #[clap(long, value_name = "SECS")]
+    pub duration: Option<u64>,
+
+    /// Seconds to run before measuring, not counted in the results.
+    #[clap(long, default_value = "0", value_name = "SECS")]
+    pub warmup: u64,
+
+    #[clap(flatten)]
+    pub workload: WorkloadOpt,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ClientOpt {
+    /// Server's address.
+    #[clap(short, long, default_value = "127.0.0.1:8080", value_name = "ADDR")]
+    pub connect_to: SocketAddr,
+
+    #[clap(flatten)]
+    pub run: RunOpt,
+
+    #[clap(flatten)]
+    pub bench: BenchOpt,
+}
+
+/// Something to do with whichever transport the options select.
+trait Run {
+    fn run<T: Transport + 'static>(self, runtime: Runtime) -> io::Result<()>;
+
+    /// Like [`Run::run`], for transports that may move to another thread.
+    fn run_send<T: Transport + Send + 'static>(self, runtime: Runtime) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        self.run::<T>(runtime)
+    }
+}
+
+fn dispatch(opt: &BenchOpt, run: impl Run) -> io::Result<()> {
+    let runtime = opt.runtime;
+    let batching = matches!(
+        (opt.proto, runtime),
+        (Proto::Udp | Proto::Tquic, Runtime::Mio)
+    );
+    if opt.transport.batch > 1 && !batching {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--batch only applies to udp and tquic on mio",
+        ));
+    }
+    // quinn's monoio socket sends datagram by datagram.
+    let offloading = opt.proto == Proto::Quinn && runtime != Runtime::Monoio;
+    if opt.transport.gso && !(batching || offloading) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--gso only applies to quinn on tokio and async-std, and to udp and tquic on mio",
+        ));
+    }
+    if opt.transport.threads > 1 && opt.proto != Proto::Udp {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--threads only applies to udp",
+        ));
+    }
+    match (opt.proto, opt.transport.crypto) {
+        (_, None) | (Proto::Quinn, _) | (Proto::Tquic, Some(Crypto::Tls)) => {}
+        (Proto::Tquic, Some(Crypto::Plaintext)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "tquic always encrypts, its TLS stack has no plaintext mode or null cipher",
+            ))
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--crypto only applies to quinn and tquic",
+            ))
+        }
+    }
+    if opt.transport.gso && opt.transport.batch > 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--gso and --batch do not combine",
+        ));
+    }
+    match (opt.proto, runtime) {
+        (Proto::Tcp, Runtime::Native) => run.run_send::<NativeTcp>(runtime),
+        (Proto::Tcp, Runtime::Mio) => run.run::<MioTcp>(runtime),
+        (Proto::Tcp, Runtime::Tokio) => run.run::<TokioTcp>(runtime),
+        (Proto::Tcp, Runtime::Monoio) => run.run::<MonoioTcp>(runtime),
+        (Proto::Tcp, Runtime::AsyncStd) => run.run::<AsyncStdTcp>(runtime),
+        (Proto::Tls, Runtime::Native) => run.run_send::<NativeTls>(runtime),
+        (Proto::Tls, Runtime::Tokio) => run.run::<TokioTls>(runtime),
+        (Proto::Tls, Runtime::Monoio) => run.run::<MonoioTls>(runtime),
+        (Proto::Tls, Runtime::AsyncStd) => run.run::<AsyncStdTls>(runtime),
+        (Proto::Udp, Runtime::Native) => run.run::<NativeUdp>(runtime),
+        (Proto::Udp, Runtime::Mio) => run.run::<MioUdp>(runtime),
+        (Proto::Udp, Runtime::Tokio) => run.run::<TokioUdp>(runtime),
+        (Proto::Udp, Runtime::Monoio) => run.run::<MonoioUdp>(runtime),
+        (Proto::Udp, Runtime::AsyncStd) => run.run::<AsyncStdUdp>(runtime),
+        (Proto::Quinn, Runtime::Tokio) => run.run::<TokioQuinn>(runtime),
+        (Proto::Quinn, Runtime::Monoio) => run.run::<MonoioQuinn>(runtime),
+        (Proto::Quinn, Runtime::AsyncStd) => run.run::<AsyncStdQuinn>(runtime),
+        (Proto::Tquic, Runtime::Native) => run.run::<NativeTquic>(runtime),
+        (Proto::Tquic, Runtime::Mio) => run.run::<MioTquic>(runtime),
+        (Proto::Tquic, Runtime::Tokio) => run.run::<TokioTquic>(runtime),
+        (Proto::Tquic, Runtime::Monoio) => run.run::<MonoioTquic>(runtime),
+        (Proto::Tquic, Runtime::AsyncStd) => run.run::<AsyncStdTquic>(runtime),
+        (proto, runtime) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} is not implemented on {}", proto, runtime),
+        )),
+    }
+}
+
+/// Echo server, runs until killed.
+pub fn run_server(opt: &ServerOpt) -> io::Result<()> {
+    dispatch(&opt.bench, Server(opt))
+}
+
+/// Ping-pong client, runs for the configured duration or until the
+/// connection fails.
+pub fn run_client(opt: &ClientOpt) -> io::Result<()> {
+    dispatch(&opt.bench, Client(opt))
+}
+
+struct Server<'a>(&'a ServerOpt);
+
+impl Server<'_> {
+    /// Start reporting from a thread, returns what connections count into.
+    fn meter(&self, runtime: Runtime) -> io::Result<Arc<Echoed>> {
+        let bench = &self.0.bench;
+        let meter = ServerMeter::new(Reporter::new(
+            &bench.report,
+            Role::Server,
+            bench.proto,
+            runtime,
+        )?);
+        let echoed = meter.echoed();
+        std::thread::spawn(move || meter.run());
+        Ok(echoed)
+    }
+
+    /// Hand a listener to `serve` on each of `--threads` threads, each with
+    /// a runtime of its own and bound to the address the first one got.
+    /// Runs until a thread fails.
+    fn on_threads<T, F, Fut>(&self, runtime: Runtime, serve: F) -> io::Result<()>
+    where
+        T: Transport + 'static,
+        F: Fn(T::Listener) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = io::Result<()>>,
+    {
+        let transport = &self.0.bench.transport;
+        let serve = Arc::new(serve);
+        let (done_tx, done_rx) = mpsc::channel();
+        let mut addr = self.0.listen;
+        for _ in 0..transport.threads {
+            let (bound_tx, bound_rx) = mpsc::sync_channel(1);
+            let (serve, done, transport) = (serve.clone(), done_tx.clone(), transport.clone());
+            thread::spawn(move || {
+                let res = runtime.block_on(async {
+                    let listener = T::bind(addr, &transport).await?;
+                    let _ = bound_tx.send(listener.local_addr());
+                    serve(listener).await
+                });
+                let _ = done.send(res.and_then(|res| res));
+            });
+            // The next threads bind once this one has, to its port.
+            addr = match bound_rx.recv() {
+                Ok(bound) => bound?,
+                Err(_) => return done_rx.recv().expect("threads report how they end"),
+            };
+        }
+        println!("listening on {}", addr);
+        done_rx.recv().expect("threads report how they end")
+    }
+}
+
+impl Run for Server<'_> {
+    fn run<T: Transport + 'static>(self, runtime: Runtime) -> io::Result<()> {
+        let echoed = self.meter(runtime)?;
+        self.on_threads::<T, _, _>(runtime, move |listener| {
+            let echoed = echoed.clone();
+            async move {
+                driver::serve(listener, |conn| runtime.spawn(echo(conn, echoed.clone()))).await
+            }
+        })
+    }
+
+    /// Blocking transports get a thread per connection.
+    fn run_send<T: Transport + Send + 'static>(self, runtime: Runtime) -> io::Result<()> {
+        let echoed = self.meter(runtime)?;
+        self.on_threads::<T, _, _>(runtime, move |listener| {
+            let echoed = echoed.clone();
+            async move {
+                driver::serve(listener, |conn| {
+                    let echoed = echoed.clone();
+                    std::thread::spawn(move || runtime.block_on(echo(conn, echoed)));
+                })
+                .await
+            }
+        })
+    }
+}
+
+async fn echo<T: Transport>(conn: T, echoed: Arc<Echoed>) {
+    if let Err(e) = driver::echo(conn, echoed).await {
+        error!("connection error: {}", e);
+    }
+}
+
+struct Client<'a>(&'a ClientOpt);
+
+impl Client<'_> {
+    /// Run one connection to completion on a runtime of its own, with a flow
+    /// on each of its streams measured by the meter of the same index.
+    fn flow<T: Transport>(&self, runtime: Runtime, mut meters: Vec<Meter>) -> io::Result<()> {
+        let opt = self.0;
+        runtime.block_on(async {
+            let mut streams = vec![T::connect(opt.connect_to, &opt.bench.transport).await?];
+            for _ in 1..meters.len() {
+                let stream = streams[0].open_stream(StreamKind::Bi).await?;
+                streams.push(stream);
+            }
+            let flows = streams
+                .iter_mut()
+                .zip(&mut meters)
+                .map(|(stream, meter)| driver::ping_pong(stream, meter, &opt.run.workload))
+                .collect();
+            let res: io::Result<()> = join_all(flows).await.into_iter().collect();
+            // The connection closes with the stream it was opened with.
+            streams.truncate(1);
+            let conn = streams.pop().expect("the connected stream");
+            res.and(conn.close_connection().await)
+        })?
+    }
+}
+
+impl Run for Client<'_> {
+    fn run<T: Transport + 'static>(self, runtime: Runtime) -> io::Result<()> {
+        let opt = self.0;
+        let reporter = Reporter::new(&opt.bench.report, Role::Client, opt.bench.proto, runtime)?;
+        let mut meter = Meter::new(reporter).with_warmup(Duration::from_secs(opt.run.warmup));
+        if let Some(duration) = opt.run.duration {
+            meter = meter.with_duration(Duration::from_secs(duration));
+        }
+        let flows = opt
+            .run
+            .workload
+            .connections
+            .unwrap_or_else(|| opt.bench.transport.threads.into());
+        let streams = opt.run.workload.streams();
+        if streams > 1 && !matches!(opt.bench.proto, Proto::Quinn | Proto::Tquic) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--streams only applies to quinn and tquic",
+            ));
+        }
+        if streams > 1 && matches!(runtime, Runtime::Native | Runtime::Mio) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "streams of a connection run concurrently, which blocking runtimes cannot",
+            ));
+        }
+        if let Some(kind) = opt.run.workload.stream_per_message {
+            if !matches!(opt.bench.proto, Proto::Quinn | Proto::Tquic) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--stream-per-message only applies to quinn and tquic",
+                ));
+            }
+            if opt.run.workload.inflight.is_some_and(|n| n > 1) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--stream-per-message waits for each response before the next message",
+                ));
+            }
+            if kind == StreamKind::Uni && streams > 1 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "unidirectional responses are told apart by order alone, \
+                     one stream per connection",
+                ));
+            }
+        }
+        if flows == 1 && streams == 1 {
+            return self.flow::<T>(runtime, vec![meter]);
+        }
+        if flows > 1
+            && opt.bench.proto == Proto::Tquic
+            && matches!(runtime, Runtime::Native | Runtime::Mio)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "tquic servers on blocking runtimes serve one connection at a time",
+            ));
+        }
+
+        // Every flow comes from a port of its own, for the server's kernel
+        // to spread them over its sockets.
+        let shared = Arc::new(Mutex::new(meter));
+        shared.lock().expect("meter poisoned").start();
+        let client = &self;
+        let res = thread::scope(|s| {
+            let flows: Vec<_> = (0..flows)
+                .map(|_| {
+                    let meter = Meter::flow(&shared);
+                    let mut meters: Vec<_> = (1..streams).map(|_| meter.stream()).collect();
+                    meters.insert(0, meter);
+                    s.spawn(move || client.flow::<T>(runtime, meters))
+                })
+                .collect();
+            flows
+                .into_iter()
+                .map(|flow| flow.join().expect("flow panicked"))
+                .fold(Ok(()), io::Result::and)
+        });
+        shared.lock().expect("meter poisoned").finish();
+        res
+    }
+}
@@ -0,0 +1,42 @@
+use clap::{Parser, Subcommand};
+use tunnel_benchmark::bench::{self, ClientOpt, ServerOpt};
+use tunnel_benchmark::local::{self, LocalOpt};
+use tunnel_benchmark::proxy::{self, ProxyOpt};
+use tunnel_benchmark::sweep::{self, SweepOpt};
+
+#[derive(Parser, Debug)]
+#[clap(name = "bench", about = "Client-server ping-pong benchmarks")]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the echo server.
+    Server(ServerOpt),
+    /// Run the ping-pong client against a server.
+    Client(ClientOpt),
+    /// Run a server in the background and the client against it.
+    Local(LocalOpt),
+    /// Run servers and clients over a grid of settings and tabulate them.
+    Sweep(SweepOpt),
+    /// Relay between client and server, impairing the traffic.
+    Proxy(ProxyOpt),
+}
+
+fn main() {
+    env_logger::init();
+
+    let res = match Cli::parse().command {
+        Command::Server(opt) => bench::run_server(&opt),
+        Command::Client(opt) => bench::run_client(&opt),
+        Command::Local(opt) => local::run(&opt),
+        Command::Sweep(opt) => sweep::run(&opt),
+        Command::Proxy(opt) => proxy::run(&opt),
+    };
+    if let Err(e) = res {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
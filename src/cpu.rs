@@ -0,0 +1,56 @@
+//! CPU time spent by this process, user and system combined.
+
+use std::io;
+use std::mem::MaybeUninit;
+use std::time::Duration;
+
+/// CPU time used by all threads of the process so far.
+pub fn process_time() -> io::Result<Duration> {
+    let mut usage = MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage only writes into the struct we hand it.
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: initialized by the successful call above.
+    let usage = unsafe { usage.assume_init() };
+    Ok(timeval(usage.ru_utime) + timeval(usage.ru_stime))
+}
+
+fn timeval(tv: libc::timeval) -> Duration {
+    Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+}
+
+/// Measures CPU time between successive laps.
+pub struct CpuClock {
+    started: Duration,
+    last: Duration,
+}
+
+impl CpuClock {
+    pub fn new() -> Self {
+        let now = process_time().unwrap_or_default();
+        Self {
+            started: now,
+            last: now,
+        }
+    }
+
+    /// CPU time since the previous lap, or since the clock was created.
+    pub fn lap(&mut self) -> Duration {
+        let now = process_time().unwrap_or(self.last);
+        let lap = now.saturating_sub(self.last);
+        self.last = now;
+        lap
+    }
+
+    /// CPU time since the clock was created, up to the last lap.
+    pub fn total(&self) -> Duration {
+        self.last - self.started
+    }
+}
+
+impl Default for CpuClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,542 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clap::Args;
+use log::debug;
+
+use crate::batch::{Batch, BATCH_SIZE};
+use crate::meter::Echoed;
+use crate::verify::{Check, Payload, SEQ_LEN};
+use crate::window::Window;
+use crate::{Delivery, Listener, Meter, StreamKind, Transport};
+
+/// Bytes written per round trip on stream transports.
+pub const STREAM_PAYLOAD: usize = 1 << 18;
+
+/// Datagram size, kept under a 1500 bytes MTU.
+pub const DATAGRAM_PAYLOAD: usize = 1460;
+
+/// Largest UDP payload.
+const MAX_DATAGRAM: usize = 1 << 16;
+
+/// Datagrams primed before the client starts echoing back.
+pub const DATAGRAM_WINDOW: usize = 100;
+
+/// Smallest payload, room for the sequence number.
+const MIN_PAYLOAD: u64 = SEQ_LEN as u64;
+
+/// Shape of the client's traffic, defaults depend on the transport's
+/// [`Delivery`].
+#[derive(Args, Debug, Clone, Default)]
+pub struct WorkloadOpt {
+    /// Bytes per message [default: 262144 for streams, 1460 for datagrams].
+    #[clap(long, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(MIN_PAYLOAD..))]
+    pub payload_size: Option<u64>,
+
+    /// Messages sent before waiting for an echo [default: 1 for streams, 100
+    /// for datagrams]. Streams leave at most 256 KiB unechoed and read echoes
+    /// before writing more.
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub inflight: Option<u64>,
+
+    /// Fill every message with a pattern derived from its sequence number
+    /// and check every echoed byte against it.
+    #[clap(long)]
+    pub verify: bool,
+
+    /// Milliseconds after which a datagram counts as lost and is replaced
+    /// [default: adapts to the measured round trips].
+    #[clap(long, value_name = "MS")]
+    pub loss_timeout: Option<u64>,
+
+    /// Connections to open, each on a thread and runtime of its own
+    /// [default: --threads].
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..=1024))]
+    pub connections: Option<u64>,
+
+    /// Streams per connection, each running the workload concurrently with
+    /// the others [default: 1]. Only QUIC multiplexes streams.
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..=100))]
+    pub streams: Option<u64>,
+
+    /// Send every message on a fresh stream, finished right after it, and
+    /// wait for the whole response before the next, to measure what opening
+    /// and closing streams costs. A unidirectional message is answered on a
+    /// stream of the server's. Only QUIC.
+    #[clap(long, value_enum, value_name = "KIND")]
+    pub stream_per_message: Option<StreamKind>,
+}
+
+impl WorkloadOpt {
+    pub fn payload_size(&self, delivery: Delivery) -> usize {
+        match (self.payload_size, delivery) {
+            (Some(size), _) => size as usize,
+            (None, Delivery::Stream) => STREAM_PAYLOAD,
+            (None, Delivery::Datagram) => DATAGRAM_PAYLOAD,
+        }
+    }
+
+    pub fn inflight(&self, delivery: Delivery) -> usize {
+        match (self.inflight, delivery) {
+            (Some(n), _) => n as usize,
+            (None, Delivery::Stream) => 1,
+            (None, Delivery::Datagram) => DATAGRAM_WINDOW,
+        }
+    }
+
+    pub fn streams(&self) -> usize {
+        self.streams.unwrap_or(1) as usize
+    }
+
+    fn loss_timeout(&self) -> Option<Duration> {
+        self.loss_timeout.map(Duration::from_millis)
+    }
+}
+
+/// Client side of the ping-pong benchmark, runs until the meter is done or
+/// the connection fails, closes it and then prints the summary of the run.
+pub async fn ping_pong<T: Transport>(
+    conn: &mut T,
+    meter: &mut Meter,
+    workload: &WorkloadOpt,
+) -> io::Result<()> {
+    let payload_size = workload.payload_size(T::DELIVERY);
+    let inflight = workload.inflight(T::DELIVERY);
+    let payload = Payload::new(payload_size, workload.verify);
+    if workload.verify || T::DELIVERY == Delivery::Datagram {
+        meter.track_integrity();
+    }
+    meter.start();
+    let res = match (T::DELIVERY, workload.stream_per_message) {
+        (Delivery::Stream, Some(kind)) => {
+            let payload = workload.verify.then_some(payload);
+            message_ping_pong(conn, meter, payload_size, kind, payload).await
+        }
+        (Delivery::Stream, None) => {
+            let payload = workload.verify.then_some(payload);
+            stream_ping_pong(conn, meter, payload_size, inflight, payload).await
+        }
+        (Delivery::Datagram, _) => {
+            let window = Window::new(workload.loss_timeout());
+            datagram_ping_pong(conn, meter, payload, payload_size, inflight, window).await
+        }
+    };
+    let res = match res {
+        Ok(()) => conn.close().await,
+        Err(e) => Err(e),
+    };
+    meter.finish();
+    res
+}
+
+/// Up to `inflight` payloads outstanding, a round trip ends with the last
+/// echoed byte of its payload. Payloads are only numbered when verified.
+///
+/// At most [`STREAM_WINDOW`] bytes go unechoed: past that the client reads
+/// echoes before writing on, larger payloads a part at a time. Otherwise both
+/// sides could end up blocked writing to a peer that is not reading.
+async fn stream_ping_pong<T: Transport>(
+    conn: &mut T,
+    meter: &mut Meter,
+    payload_size: usize,
+    inflight: usize,
+    mut verified: Option<Payload>,
+) -> io::Result<()> {
+    let mut buf = vec![0; payload_size];
+    let mut echoes = Echoes::new(payload_size, inflight);
+    let mut sent = 0;
+    while !meter.is_done() {
+        while echoes.sent_at.len() < inflight && !meter.is_done() {
+            if let Some(payload) = &verified {
+                payload.write(&mut buf, sent);
+            }
+            sent += 1;
+            for (i, part) in buf.chunks(STREAM_WINDOW).enumerate() {
+                while echoes.unechoed + part.len() > STREAM_WINDOW {
+                    echoes.recv(conn, meter, &mut verified).await?;
+                }
+                if i == 0 {
+                    echoes.sent_at.push_back(Instant::now());
+                }
+                conn.send(part).await?;
+                echoes.unechoed += part.len();
+            }
+        }
+        if echoes.unechoed > 0 {
+            echoes.recv(conn, meter, &mut verified).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Bytes a stream client leaves unechoed at most, what the default payload
+/// puts in flight.
+const STREAM_WINDOW: usize = STREAM_PAYLOAD;
+
+/// Echoes a stream client is waiting for.
+struct Echoes {
+    buf: Vec<u8>,
+    len: usize,
+    /// Payloads echoed in full.
+    echoed: u64,
+    /// When each payload not echoed in full was sent.
+    sent_at: VecDeque<Instant>,
+    /// Bytes sent and not echoed yet.
+    unechoed: usize,
+}
+
+impl Echoes {
+    fn new(payload_size: usize, inflight: usize) -> Self {
+        Self {
+            buf: vec![0; payload_size],
+            len: 0,
+            echoed: 0,
+            sent_at: VecDeque::with_capacity(inflight),
+            unechoed: 0,
+        }
+    }
+
+    /// Receive more of the current echo, and record its round trip once it
+    /// is complete.
+    async fn recv<T: Transport>(
+        &mut self,
+        conn: &mut T,
+        meter: &mut Meter,
+        verified: &mut Option<Payload>,
+    ) -> io::Result<()> {
+        let n = conn.recv(&mut self.buf[self.len..]).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.len += n;
+        self.unechoed = self.unechoed.saturating_sub(n);
+        if self.len == self.buf.len() {
+            self.len = 0;
+            if let Some(payload) = verified {
+                meter.count(payload.check_in_order(&self.buf, self.echoed));
+            }
+            self.echoed += 1;
+            let sent_at = self.sent_at.pop_front().expect("echo of an unsent payload");
+            meter.record(self.buf.len(), sent_at.elapsed());
+        }
+        Ok(())
+    }
+}
+
+/// One payload per stream of `kind`, a round trip lasts from opening the
+/// stream until the end of the response.
+async fn message_ping_pong<T: Transport>(
+    conn: &mut T,
+    meter: &mut Meter,
+    payload_size: usize,
+    kind: StreamKind,
+    mut verified: Option<Payload>,
+) -> io::Result<()> {
+    let mut buf = vec![0; payload_size];
+    // One byte more than sent, so longer responses do not pass for intact.
+    let mut echo = vec![0; payload_size + 1];
+    let mut sent = 0;
+    while !meter.is_done() {
+        let started = Instant::now();
+        let mut stream = conn.open_stream(kind).await?;
+        if let Some(payload) = &mut verified {
+            payload.write(&mut buf, sent);
+        }
+        stream.send(&buf).await?;
+        stream.finish().await?;
+        let mut echo_len = 0;
+        loop {
+            let n = stream.recv(&mut echo[echo_len..]).await?;
+            if n == 0 {
+                break;
+            }
+            echo_len += n;
+            if echo_len == echo.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "response longer than the message",
+                ));
+            }
+        }
+        if echo_len < payload_size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if let Some(payload) = &mut verified {
+            meter.count(payload.check_in_order(&echo[..payload_size], sent));
+        }
+        sent += 1;
+        meter.record(payload_size, started.elapsed());
+    }
+    Ok(())
+}
+
+/// A window of `inflight` numbered datagrams, topped up whenever one is
+/// echoed or given up as lost.
+async fn datagram_ping_pong<T: Transport>(
+    conn: &mut T,
+    meter: &mut Meter,
+    mut payload: Payload,
+    payload_size: usize,
+    inflight: usize,
+    mut window: Window,
+) -> io::Result<()> {
+    // One byte more than sent, so longer echoes do not pass for intact.
+    let mut batch = Batch::new(BATCH_SIZE, payload_size + 1);
+    while !meter.is_done() {
+        batch.clear();
+        while window.len() < inflight {
+            if batch.is_full() {
+                conn.send_batch(&batch).await?;
+                batch.clear();
+            }
+            let seq = window.send();
+            payload.write(batch.push(payload_size), seq);
+        }
+        if !batch.is_empty() {
+            conn.send_batch(&batch).await?;
+        }
+        // Waiting one timeout at most declares a datagram lost between one
+        // and two timeouts after it was sent.
+        conn.recv_batch(&mut batch, Some(window.timeout())).await?;
+        for echo in batch.iter() {
+            let (check, rtt) = match payload.read(echo) {
+                Some(seq) => window.echoed(seq),
+                None => (Check::Corrupt, None),
+            };
+            meter.count(check);
+            // Only datagrams still in flight have a round trip.
+            if let Some(rtt) = rtt {
+                meter.record(echo.len(), rtt);
+            }
+        }
+        let lost = window.expire(Instant::now());
+        meter.lost(lost);
+    }
+    Ok(())
+}
+
+/// Server side of the ping-pong benchmark. Streams are echoed until the peer
+/// closes and then closed on this side too, datagrams for as long as the
+/// socket lasts.
+pub async fn echo<T: Transport>(mut conn: T, echoed: Arc<Echoed>) -> io::Result<()> {
+    if T::DELIVERY == Delivery::Datagram {
+        return echo_datagrams(conn, echoed).await;
+    }
+    let mut buf = vec![0; STREAM_PAYLOAD];
+    loop {
+        let n = conn.recv(&mut buf).await?;
+        if n == 0 {
+            debug!("received 0, done");
+            return conn.close().await;
+        }
+        conn.send(&buf[..n]).await?;
+        echoed.add(n);
+    }
+}
+
+/// Echo datagrams a batch at a time, for as long as the socket lasts. The
+/// session serves every flow sent to it, none of them ends it.
+async fn echo_datagrams<T: Transport>(mut conn: T, echoed: Arc<Echoed>) -> io::Result<()> {
+    let mut batch = Batch::new(BATCH_SIZE, MAX_DATAGRAM);
+    loop {
+        conn.recv_batch(&mut batch, None).await?;
+        conn.send_batch(&batch).await?;
+        for buf in batch.iter() {
+            echoed.add(buf.len());
+        }
+    }
+}
+
+/// Accept connections forever, handing each one to `spawn`.
+pub async fn serve<L: Listener>(mut listener: L, mut spawn: impl FnMut(L::Conn)) -> io::Result<()> {
+    loop {
+        let conn = listener.accept().await?;
+        spawn(conn);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use clap::ValueEnum;
+
+    use super::*;
+    use crate::quinn::{AsyncStdQuinn, MonoioQuinn, TokioQuinn};
+    use crate::runtime::{block_on, join_all, Runtime};
+    use crate::tcp::NativeTcp;
+    use crate::tls::{CipherSuite, NativeTls};
+    use crate::tquic::{MonoioTquic, TokioTquic};
+    use crate::udp::NativeUdp;
+    use crate::TransportOpt;
+
+    /// Start a server on a thread of its own, echoing every connection.
+    fn server<T: Transport + 'static>(runtime: Runtime, opt: &TransportOpt) -> SocketAddr {
+        let (bound_tx, bound_rx) = mpsc::channel();
+        let opt = opt.clone();
+        thread::spawn(move || {
+            runtime.block_on(async {
+                let listener = T::bind("127.0.0.1:0".parse().unwrap(), &opt).await?;
+                bound_tx.send(listener.local_addr()?).unwrap();
+                serve(listener, |conn| {
+                    runtime.spawn(async {
+                        let _ = echo(conn, Arc::default()).await;
+                    })
+                })
+                .await
+            })
+        });
+        bound_rx.recv().expect("server bound")
+    }
+
+    /// Send `msg` and read as many bytes back.
+    async fn round_trip<T: Transport>(conn: &mut T, msg: &[u8]) -> io::Result<Vec<u8>> {
+        conn.send(msg).await?;
+        let mut buf = vec![0; msg.len()];
+        let mut read = 0;
+        while read < buf.len() {
+            match conn.recv(&mut buf[read..]).await? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => read += n,
+            }
+        }
+        Ok(buf)
+    }
+
+    /// Connect, echo `msg` once and close.
+    fn echo_once<T: Transport>(
+        runtime: Runtime,
+        addr: SocketAddr,
+        opt: &TransportOpt,
+        msg: &[u8],
+    ) -> io::Result<Vec<u8>> {
+        runtime.block_on(async {
+            let mut conn = T::connect(addr, opt).await?;
+            let echoed = round_trip(&mut conn, msg).await?;
+            conn.close().await?;
+            conn.close_connection().await?;
+            Ok(echoed)
+        })?
+    }
+
+    /// Echo a message of its own on each of `streams` streams of one
+    /// connection, all at once.
+    fn echo_on_streams<T: Transport>(
+        runtime: Runtime,
+        addr: SocketAddr,
+        opt: &TransportOpt,
+        streams: u8,
+    ) -> io::Result<()> {
+        runtime.block_on(async {
+            let mut conns = vec![T::connect(addr, opt).await?];
+            for _ in 1..streams {
+                let stream = conns[0].open_stream(StreamKind::Bi).await?;
+                conns.push(stream);
+            }
+            let msgs: Vec<_> = (0..streams).map(|i| vec![i; 4096]).collect();
+            let trips = conns
+                .iter_mut()
+                .zip(&msgs)
+                .map(|(conn, msg)| round_trip(conn, msg))
+                .collect();
+            for (echoed, msg) in join_all(trips).await.into_iter().zip(&msgs) {
+                assert_eq!(&echoed?, msg);
+            }
+            for conn in &mut conns {
+                conn.close().await?;
+            }
+            conns.truncate(1);
+            conns.remove(0).close_connection().await
+        })?
+    }
+
+    #[test]
+    fn echo_over_native_tcp() {
+        let opt = TransportOpt::default();
+        let addr = server::<NativeTcp>(Runtime::Native, &opt);
+        let echoed = echo_once::<NativeTcp>(Runtime::Native, addr, &opt, b"ping").unwrap();
+        assert_eq!(echoed, b"ping");
+    }
+
+    #[test]
+    fn echo_over_udp_past_an_empty_datagram() {
+        let opt = TransportOpt::default();
+        let addr = server::<NativeUdp>(Runtime::Native, &opt);
+        block_on(async {
+            let mut conn = NativeUdp::connect(addr, &opt).await.unwrap();
+            let mut buf = [0; 16];
+            for msg in [&b""[..], b"ping"] {
+                conn.send(msg).await.unwrap();
+                let timeout = Duration::from_secs(1);
+                let n = conn.recv_timeout(&mut buf, timeout).await.unwrap();
+                assert_eq!(n.map(|n| &buf[..n]), Some(msg));
+            }
+        });
+    }
+
+    #[test]
+    fn echo_over_tls_with_each_cipher_suite() {
+        let only = |suite: CipherSuite| TransportOpt {
+            cipher_suites: vec![suite],
+            ..TransportOpt::default()
+        };
+        for &suite in CipherSuite::value_variants() {
+            let opt = only(suite);
+            let addr = server::<NativeTls>(Runtime::Native, &opt);
+            let echoed = echo_once::<NativeTls>(Runtime::Native, addr, &opt, b"ping").unwrap();
+            assert_eq!(echoed, b"ping", "{}", suite);
+        }
+
+        // Without a suite in common there is no handshake.
+        let addr = server::<NativeTls>(Runtime::Native, &only(CipherSuite::Aes128Gcm));
+        let client = only(CipherSuite::Chacha20Poly1305);
+        assert!(echo_once::<NativeTls>(Runtime::Native, addr, &client, b"ping").is_err());
+    }
+
+    #[test]
+    fn echo_over_tquic_on_monoio() {
+        let opt = TransportOpt::default();
+        let addr = server::<MonoioTquic>(Runtime::Monoio, &opt);
+        let msg: Vec<_> = (0..=255).cycle().take(4096).collect();
+        let echoed = echo_once::<MonoioTquic>(Runtime::Monoio, addr, &opt, &msg).unwrap();
+        assert_eq!(echoed, msg);
+    }
+
+    #[test]
+    fn echo_over_quinn_on_monoio() {
+        let opt = TransportOpt::default();
+        let addr = server::<MonoioQuinn>(Runtime::Monoio, &opt);
+        let msg: Vec<_> = (0..=255).cycle().take(4096).collect();
+        let echoed = echo_once::<MonoioQuinn>(Runtime::Monoio, addr, &opt, &msg).unwrap();
+        assert_eq!(echoed, msg);
+        echo_on_streams::<MonoioQuinn>(Runtime::Monoio, addr, &opt, 4).unwrap();
+    }
+
+    #[test]
+    fn echo_over_quinn_on_async_std() {
+        let opt = TransportOpt::default();
+        let addr = server::<AsyncStdQuinn>(Runtime::AsyncStd, &opt);
+        let msg: Vec<_> = (0..=255).cycle().take(4096).collect();
+        let echoed = echo_once::<AsyncStdQuinn>(Runtime::AsyncStd, addr, &opt, &msg).unwrap();
+        assert_eq!(echoed, msg);
+        echo_on_streams::<AsyncStdQuinn>(Runtime::AsyncStd, addr, &opt, 4).unwrap();
+    }
+
+    #[test]
+    fn echo_on_quinn_streams() {
+        let opt = TransportOpt::default();
+        let addr = server::<TokioQuinn>(Runtime::Tokio, &opt);
+        echo_on_streams::<TokioQuinn>(Runtime::Tokio, addr, &opt, 4).unwrap();
+    }
+
+    #[test]
+    fn echo_on_tquic_streams() {
+        let opt = TransportOpt::default();
+        let addr = server::<TokioTquic>(Runtime::Tokio, &opt);
+        echo_on_streams::<TokioTquic>(Runtime::Tokio, addr, &opt, 4).unwrap();
+    }
+}
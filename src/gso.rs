@@ -0,0 +1,377 @@
+//! UDP segmentation offload on Linux: one sendmsg carries a run of equally
+//! sized datagrams (UDP_SEGMENT), one recvmsg returns several the kernel
+//! coalesced (UDP_GRO).
+
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::RawFd;
+
+/// Datagrams one send may carry, UDP_MAX_SEGMENTS of older kernels.
+#[cfg(target_os = "linux")]
+const MAX_SEGMENTS: usize = 64;
+
+/// Bytes one send may carry, the largest UDP payload.
+#[cfg(target_os = "linux")]
+const MAX_SEND: usize = 65507;
+
+/// Room for the datagrams one receive may return.
+const MAX_RECV: usize = 1 << 16;
+
+/// Whether the kernel takes UDP_SEGMENT on this socket.
+#[cfg(target_os = "linux")]
+pub(crate) fn gso_supported(fd: RawFd) -> bool {
+    let mut size: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: `size` and `len` are valid for the option's value.
+    let res = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_UDP,
+            libc::UDP_SEGMENT,
+            &mut size as *mut _ as *mut _,
+            &mut len,
+        )
+    };
+    res == 0
+}
+
+/// Ask the kernel to coalesce received datagrams, false if it refuses.
+#[cfg(target_os = "linux")]
+pub(crate) fn enable_gro(fd: RawFd) -> bool {
+    let on: libc::c_int = 1;
+    // SAFETY: `on` is valid for the option's value.
+    let res = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_UDP,
+            libc::UDP_GRO,
+            &on as *const _ as *const _,
+            std::mem::size_of::<libc::c_int>() as _,
+        )
+    };
+    res == 0
+}
+
+/// Whether a segmented send failed because the kernel or the device cannot
+/// segment, plain sends may still work.
+pub(crate) fn refused(e: &io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    let codes = [libc::EIO, libc::EINVAL, libc::ENOPROTOOPT, libc::EOPNOTSUPP];
+    #[cfg(not(target_os = "linux"))]
+    let codes = [];
+    e.kind() == io::ErrorKind::Unsupported || e.raw_os_error().is_some_and(|c| codes.contains(&c))
+}
+
+/// Send `pkts` to `dst`, each run of equally sized ones with a single
+/// sendmsg. Returns how many went out before the socket would block,
+/// `WouldBlock` if none did.
+#[cfg(target_os = "linux")]
+pub(crate) fn send_segments(fd: RawFd, pkts: &[&[u8]], dst: SocketAddr) -> io::Result<usize> {
+    let (mut addr, addr_len) = crate::batch::sockaddr(&dst);
+    let mut sent = 0;
+    while sent < pkts.len() {
+        let run = &pkts[sent..sent + run_len(&pkts[sent..])];
+        match send_run(fd, run, &mut addr, addr_len) {
+            Ok(()) => sent += run.len(),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock && sent > 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn gso_supported(_fd: RawFd) -> bool {
+    false
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn enable_gro(_fd: RawFd) -> bool {
+    false
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn send_segments(_fd: RawFd, _pkts: &[&[u8]], _dst: SocketAddr) -> io::Result<usize> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// How many datagrams at the front of `pkts` one send carries: those as
+/// long as the first, and a last shorter one.
+#[cfg(target_os = "linux")]
+fn run_len(pkts: &[&[u8]]) -> usize {
+    let size = pkts[0].len();
+    if size == 0 {
+        return 1;
+    }
+    let max = MAX_SEGMENTS.min(MAX_SEND / size);
+    let mut n = 1;
+    while n < max && n < pkts.len() {
+        let len = pkts[n].len();
+        if len == 0 || len > size {
+            break;
+        }
+        n += 1;
+        if len < size {
+            break;
+        }
+    }
+    n
+}
+
+#[cfg(target_os = "linux")]
+fn send_run(
+    fd: RawFd,
+    run: &[&[u8]],
+    addr: &mut libc::sockaddr_storage,
+    addr_len: libc::socklen_t,
+) -> io::Result<()> {
+    let mut iovs: Vec<libc::iovec> = run
+        .iter()
+        .map(|pkt| libc::iovec {
+            iov_base: pkt.as_ptr() as *mut _,
+            iov_len: pkt.len(),
+        })
+        .collect();
+    // Aligned for a cmsghdr, large enough for one carrying a u16.
+    let mut control = [0u64; 4];
+    // SAFETY: a zeroed msghdr is valid, the pointers set below outlive the
+    // call.
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_name = addr as *mut _ as *mut _;
+    msg.msg_namelen = addr_len;
+    msg.msg_iov = iovs.as_mut_ptr();
+    msg.msg_iovlen = iovs.len() as _;
+    if run.len() > 1 {
+        msg.msg_control = control.as_mut_ptr() as *mut _;
+        // SAFETY: the control buffer holds CMSG_SPACE(2) bytes, so the first
+        // header and its data are inside it.
+        unsafe {
+            msg.msg_controllen = libc::CMSG_SPACE(std::mem::size_of::<u16>() as _) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_UDP;
+            (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<u16>() as _) as _;
+            std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u16, run[0].len() as u16);
+        }
+    }
+    // SAFETY: `msg` points to live buffers of the lengths it gives.
+    match unsafe { libc::sendmsg(fd, &msg, 0) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Receives with UDP_GRO on, handing out what one recvmsg returned a
+/// datagram at a time. A datagram longer than the buffer it is read into is
+/// handed out over several reads rather than cut short.
+pub(crate) struct GroReader {
+    buf: Vec<u8>,
+    len: usize,
+    pos: usize,
+    segment: usize,
+    /// Datagrams of the last receive not handed out yet.
+    left: usize,
+    source: Option<SocketAddr>,
+}
+
+impl GroReader {
+    pub(crate) fn new() -> Self {
+        Self {
+            buf: vec![0; MAX_RECV],
+            len: 0,
+            pos: 0,
+            segment: 0,
+            left: 0,
+            source: None,
+        }
+    }
+
+    /// Next datagram and its source, or as much of it as fits in `out`,
+    /// receiving only once all of the last receive are handed out.
+    /// `WouldBlock` when nothing is queued.
+    pub(crate) fn recv_from(
+        &mut self,
+        fd: RawFd,
+        out: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr)> {
+        if self.left == 0 {
+            self.fill(fd)?;
+        }
+        let end = match self.segment {
+            0 => self.pos,
+            segment => (self.pos / segment + 1) * segment,
+        }
+        .min(self.len);
+        let n = (end - self.pos).min(out.len());
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        if self.pos == end {
+            self.left -= 1;
+        }
+        let source = self
+            .source
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown address family"))?;
+        Ok((n, source))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn fill(&mut self, fd: RawFd) -> io::Result<()> {
+        // SAFETY: all zeroes is a valid sockaddr_storage.
+        let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        let mut iov = libc::iovec {
+            iov_base: self.buf.as_mut_ptr() as *mut _,
+            iov_len: self.buf.len(),
+        };
+        // Aligned for a cmsghdr, large enough for one carrying a c_int.
+        let mut control = [0u64; 4];
+        // SAFETY: as in `send_run`.
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_name = &mut addr as *mut _ as *mut _;
+        msg.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as _;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut _;
+        msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+        let len = loop {
+            // SAFETY: `msg` points to live buffers of the lengths it gives.
+            let n = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+            if n >= 0 {
+                break n as usize;
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        };
+
+        // Without a segment size the kernel returned a single datagram.
+        let mut segment = len;
+        // SAFETY: the kernel filled `msg_controllen` bytes of valid headers.
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_UDP && (*cmsg).cmsg_type == libc::UDP_GRO {
+                    let size =
+                        std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                    segment = size as usize;
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+        self.len = len;
+        self.pos = 0;
+        self.segment = segment;
+        // An empty datagram is still one.
+        self.left = match segment {
+            0 => 1,
+            segment => len.div_ceil(segment),
+        };
+        self.source = crate::batch::socket_addr(&addr);
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn fill(&mut self, _fd: RawFd) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::net::UdpSocket;
+    use std::os::fd::AsRawFd;
+
+    use super::*;
+
+    fn runs(lens: &[usize]) -> Vec<usize> {
+        let bufs: Vec<_> = lens.iter().map(|&len| vec![0; len]).collect();
+        let mut pkts: Vec<_> = bufs.iter().map(Vec::as_slice).collect();
+        let mut runs = Vec::new();
+        while !pkts.is_empty() {
+            let n = run_len(&pkts);
+            runs.push(n);
+            pkts.drain(..n);
+        }
+        runs
+    }
+
+    #[test]
+    fn groups_equal_sizes_and_a_shorter_last() {
+        assert_eq!(runs(&[1200; 3]), [3]);
+        assert_eq!(runs(&[1200, 1200, 500, 1200]), [3, 1]);
+        assert_eq!(runs(&[500, 1200, 1200]), [1, 2]);
+        assert_eq!(runs(&[1200, 0, 1200]), [1, 1, 1]);
+        assert_eq!(runs(&[0, 0]), [1, 1]);
+        // Capped by the segment count, then by the bytes of one send.
+        assert_eq!(runs(&[100; 130]), [64, 64, 2]);
+        assert_eq!(runs(&[30000; 5]), [2, 2, 1]);
+    }
+
+    /// A reader holding what a receive of `lens` coalesced returned.
+    fn coalesced(lens: &[usize]) -> GroReader {
+        let mut reader = GroReader::new();
+        let mut len = 0;
+        for (i, &n) in lens.iter().enumerate() {
+            reader.buf[len..len + n].fill(i as u8);
+            len += n;
+        }
+        reader.len = len;
+        reader.segment = lens[0];
+        reader.left = lens.len();
+        reader.source = Some(([127, 0, 0, 1], 4433).into());
+        reader
+    }
+
+    /// Read from `reader` into buffers of `size` until it needs to receive.
+    fn drain(reader: &mut GroReader, size: usize) -> Vec<Vec<u8>> {
+        let mut out = vec![0; size];
+        let mut reads = Vec::new();
+        while reader.left > 0 {
+            let (n, _) = reader.recv_from(-1, &mut out).unwrap();
+            reads.push(out[..n].to_vec());
+        }
+        reads
+    }
+
+    #[test]
+    fn splits_a_coalesced_receive() {
+        let reads = drain(&mut coalesced(&[1000, 1000, 400]), 1500);
+        assert_eq!(reads, [vec![0; 1000], vec![1; 1000], vec![2; 400]]);
+
+        // Datagrams longer than the buffer come out in pieces.
+        let reads = drain(&mut coalesced(&[1000, 1000, 400]), 600);
+        let expected = [
+            vec![0; 600],
+            vec![0; 400],
+            vec![1; 600],
+            vec![1; 400],
+            vec![2; 400],
+        ];
+        assert_eq!(reads, expected);
+    }
+
+    #[test]
+    fn receives_segmented_sends() {
+        let tx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let rx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        if !gso_supported(tx.as_raw_fd()) || !enable_gro(rx.as_raw_fd()) {
+            return;
+        }
+        let bufs: Vec<_> = (0..5u8)
+            .map(|i| vec![i; 1000 - 100 * (i / 4) as usize])
+            .collect();
+        let pkts: Vec<_> = bufs.iter().map(Vec::as_slice).collect();
+        let dst = rx.local_addr().unwrap();
+        assert_eq!(send_segments(tx.as_raw_fd(), &pkts, dst).unwrap(), 5);
+
+        // Whether or not the kernel coalesced them, the datagrams come back.
+        let mut reader = GroReader::new();
+        let mut out = [0; 1500];
+        for pkt in pkts {
+            let (n, from) = reader.recv_from(rx.as_raw_fd(), &mut out).unwrap();
+            assert_eq!((&out[..n], from), (pkt, tx.local_addr().unwrap()));
+        }
+    }
+}
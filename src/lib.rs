@@ -1,14 +1,28 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
-}
+//! Client-server ping-pong benchmarks for the protocols and runtimes we
+//! consider for atm0s-sdn.
+//!
+//! Every protocol/runtime pairing implements [`Transport`], and the workloads
+//! in [`driver`] run unchanged on top of any of them.
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+pub mod batch;
+pub mod bench;
+pub mod cpu;
+pub mod driver;
+pub mod gso;
+pub mod local;
+pub mod meter;
+pub mod proxy;
+pub mod quinn;
+pub mod report;
+pub mod runtime;
+pub mod sweep;
+pub mod tcp;
+pub mod tls;
+pub mod tquic;
+pub mod transport;
+pub mod udp;
+pub mod verify;
+pub mod window;
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use meter::Meter;
+pub use transport::{Crypto, Delivery, Listener, StreamKind, Transport, TransportOpt};
@@ -0,0 +1,107 @@
+//! `bench local`: start a server as a child process, run the client against
+//! it and stop the server again, all from one command. With impairments
+//! given, the client goes through a [`proxy`](crate::proxy) in a child
+//! process too, so the CPU time the client reports is its own.
+
+use std::env;
+use std::io::{self, BufRead, BufReader};
+use std::net::SocketAddr;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+use clap::Args;
+
+use crate::bench::{self, BenchOpt, ClientOpt, RunOpt};
+use crate::proxy::ImpairOpt;
+
+#[derive(Args, Debug, Clone)]
+pub struct LocalOpt {
+    #[clap(flatten)]
+    pub run: RunOpt,
+
+    #[clap(flatten)]
+    pub bench: BenchOpt,
+
+    #[clap(flatten)]
+    pub impair: ImpairOpt,
+}
+
+/// Run the client against a fresh server on an ephemeral loopback port. The
+/// server's own reports go to stderr, prefixed with `[server]`.
+pub fn run(opt: &LocalOpt) -> io::Result<()> {
+    let mut cmd = Command::new(env::current_exe()?);
+    cmd.arg("server")
+        .args(opt.bench.server_args())
+        .args(["--listen", "127.0.0.1:0"])
+        .args(["--output", &opt.bench.report.output.to_string()]);
+    let server = ServerProcess::spawn(&mut cmd, true)?;
+    let proxy = match opt.impair.is_active() {
+        true => Some(spawn_proxy(opt, server.addr())?),
+        false => None,
+    };
+    let connect_to = proxy.as_ref().map_or(server.addr(), ServerProcess::addr);
+
+    let client = ClientOpt {
+        connect_to,
+        run: opt.run.clone(),
+        bench: opt.bench.clone(),
+    };
+    bench::run_client(&client)
+}
+
+/// Start a `bench proxy` impairing the way to `server`.
+fn spawn_proxy(opt: &LocalOpt, server: SocketAddr) -> io::Result<ServerProcess> {
+    let listen = SocketAddr::new(server.ip(), 0);
+    let mut cmd = Command::new(env::current_exe()?);
+    cmd.arg("proxy")
+        .args(["--proto", &opt.bench.proto.to_string()])
+        .args(["--listen", &listen.to_string()])
+        .args(["--forward-to", &server.to_string()])
+        .args(opt.impair.args());
+    ServerProcess::spawn(&mut cmd, false)
+}
+
+/// A `bench server` or `bench proxy` child process, killed when dropped.
+pub(crate) struct ServerProcess {
+    child: Child,
+    addr: SocketAddr,
+}
+
+impl ServerProcess {
+    /// Spawn `cmd` and wait until the server reports the address it listens
+    /// on. The rest of its stdout goes to our stderr with `relay`, or is
+    /// discarded.
+    pub(crate) fn spawn(cmd: &mut Command, relay: bool) -> io::Result<Self> {
+        let mut child = cmd.stdout(Stdio::piped()).spawn()?;
+        let stdout = child.stdout.take().expect("piped stdout");
+        let mut lines = BufReader::new(stdout).lines();
+        for line in lines.by_ref() {
+            let line = line?;
+            let Some(addr) = line.strip_prefix("listening on ") else {
+                continue;
+            };
+            let addr = addr.parse().map_err(io::Error::other)?;
+            thread::spawn(move || {
+                for line in lines.map_while(Result::ok) {
+                    if relay {
+                        eprintln!("[server] {}", line);
+                    }
+                }
+            });
+            return Ok(Self { child, addr });
+        }
+        let status = child.wait()?;
+        Err(io::Error::other(format!("server exited with {}", status)))
+    }
+
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for ServerProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,690 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hdrhistogram::Histogram;
+
+use crate::cpu::CpuClock;
+use crate::report::{Fairness, Integrity, Reporter, Sample, SampleKind, Spread};
+use crate::verify::Check;
+
+/// Round trips above this are clamped, nothing we measure should get close.
+const MAX_RTT: Duration = Duration::from_secs(60);
+
+/// How often both sides report.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a flow hands what it recorded over, often enough for the
+/// client's intervals to stay close to [`INTERVAL`].
+const FLUSH: Duration = Duration::from_millis(100);
+
+/// Throughput, round trip latency and CPU usage, reported once per interval
+/// and for the whole run by [`Meter::finish`].
+///
+/// Measuring begins after an optional warmup, counted from [`Meter::start`],
+/// and with a duration set the meter tells the workload when to stop.
+///
+/// A client running several flows gives each a meter of its own from
+/// [`Meter::flow`], or [`Meter::stream`] for more streams of a connection,
+/// which reports through the client's.
+pub struct Meter {
+    sink: Sink,
+    interval: Duration,
+    warmup: Duration,
+    duration: Option<Duration>,
+    warming_up: bool,
+    measure_from: Instant,
+    deadline: Option<Instant>,
+    started_at: Instant,
+    chunk_at: Instant,
+    cpu: CpuClock,
+    bytes: u64,
+    packets: u64,
+    total_bytes: u64,
+    total_packets: u64,
+
+    /// Throughput of every full interval, in MB/s.
+    rates: Vec<f64>,
+
+    /// Lost and misdelivered messages, when they are tracked.
+    integrity: Option<Integrity>,
+    total_integrity: Option<Integrity>,
+
+    /// Round trips of the current interval, in nanoseconds.
+    latency: Histogram<u64>,
+
+    /// Round trips of the whole run, in nanoseconds.
+    total_latency: Histogram<u64>,
+
+    /// What each flow handed over during the run.
+    flows: Vec<FlowTotal>,
+
+    /// Connections the flows run on.
+    connections: usize,
+}
+
+/// What one flow of a client handed over during the run.
+struct FlowTotal {
+    /// Index of the connection the flow runs on.
+    connection: usize,
+    bytes: u64,
+    latency: Histogram<u64>,
+}
+
+impl FlowTotal {
+    fn new(connection: usize) -> Self {
+        Self {
+            connection,
+            bytes: 0,
+            latency: histogram(),
+        }
+    }
+}
+
+/// Where a meter's intervals go.
+enum Sink {
+    Report(Reporter),
+    /// Merged into the client's meter, as its `index`th flow.
+    Flow {
+        client: Arc<Mutex<Meter>>,
+        index: usize,
+    },
+}
+
+impl Meter {
+    pub fn new(reporter: Reporter) -> Self {
+        Self::with_sink(Sink::Report(reporter), INTERVAL)
+    }
+
+    /// Meter of one of the flows `shared` measures together, with its warmup
+    /// and duration, on a connection of its own.
+    pub fn flow(shared: &Arc<Mutex<Meter>>) -> Self {
+        let mut client = shared.lock().expect("meter poisoned");
+        let connection = client.connections;
+        client.connections += 1;
+        client.add_flow(shared, connection)
+    }
+
+    /// Meter of another flow on the connection of this one, which came from
+    /// [`Meter::flow`].
+    pub fn stream(&self) -> Self {
+        let Sink::Flow {
+            client: shared,
+            index,
+        } = &self.sink
+        else {
+            unreachable!("only flows share connections");
+        };
+        let mut client = shared.lock().expect("meter poisoned");
+        let connection = client.flows[*index].connection;
+        client.add_flow(shared, connection)
+    }
+
+    /// Meter of a new flow on `connection`, reporting through `shared`,
+    /// which holds this meter.
+    fn add_flow(&mut self, shared: &Arc<Mutex<Meter>>, connection: usize) -> Self {
+        let sink = Sink::Flow {
+            client: shared.clone(),
+            index: self.flows.len(),
+        };
+        self.flows.push(FlowTotal::new(connection));
+        Self {
+            warmup: self.warmup,
+            duration: self.duration,
+            ..Self::with_sink(sink, FLUSH)
+        }
+    }
+
+    fn with_sink(sink: Sink, interval: Duration) -> Self {
+        let latency = histogram();
+        Self {
+            sink,
+            interval,
+            warmup: Duration::ZERO,
+            duration: None,
+            warming_up: false,
+            measure_from: Instant::now(),
+            deadline: None,
+            started_at: Instant::now(),
+            chunk_at: Instant::now(),
+            cpu: CpuClock::new(),
+            bytes: 0,
+            packets: 0,
+            total_bytes: 0,
+            total_packets: 0,
+            rates: Vec::new(),
+            integrity: None,
+            total_integrity: None,
+            total_latency: latency.clone(),
+            latency,
+            flows: Vec::new(),
+            connections: 0,
+        }
+    }
+
+    /// Discard what is recorded during the first `warmup` after starting.
+    pub fn with_warmup(mut self, warmup: Duration) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Be done `duration` after the warmup.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Begin the run, once the connection is set up.
+    pub fn start(&mut self) {
+        let now = Instant::now();
+        self.measure_from = now + self.warmup;
+        self.deadline = self.duration.map(|d| self.measure_from + d);
+        self.warming_up = !self.warmup.is_zero();
+        self.restart(now);
+    }
+
+    /// Report lost and misdelivered messages, see [`Meter::count`].
+    pub fn track_integrity(&mut self) {
+        self.integrity = Some(Integrity::default());
+        self.total_integrity = Some(Integrity::default());
+    }
+
+    /// Account the verification of an echo, before recording it.
+    pub fn count(&mut self, check: Check) {
+        if !self.measuring() {
+            return;
+        }
+        if let Some(integrity) = &mut self.integrity {
+            check.count(integrity);
+        }
+    }
+
+    /// Account `n` datagrams given up as lost.
+    pub fn lost(&mut self, n: u64) {
+        if !self.measuring() {
+            return;
+        }
+        if let Some(integrity) = &mut self.integrity {
+            integrity.lost += n;
+        }
+    }
+
+    /// Whether the configured duration is over.
+    pub fn is_done(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    fn restart(&mut self, now: Instant) {
+        self.started_at = now;
+        self.chunk_at = now;
+        self.cpu = CpuClock::new();
+    }
+
+    /// Whether the warmup is over, measuring from now on the first time it
+    /// is found to be.
+    fn measuring(&mut self) -> bool {
+        if self.warming_up {
+            let now = Instant::now();
+            if now < self.measure_from {
+                return false;
+            }
+            self.warming_up = false;
+            self.restart(now);
+        }
+        true
+    }
+
+    /// Account `bytes` that completed a round trip taking `rtt`.
+    pub fn record(&mut self, bytes: usize, rtt: Duration) {
+        if !self.measuring() {
+            return;
+        }
+
+        self.bytes += bytes as u64;
+        self.packets += 1;
+        self.latency.saturating_record(rtt.as_nanos() as u64);
+        self.lap();
+    }
+
+    /// End the interval once it is over, reporting it or handing it over.
+    fn lap(&mut self) {
+        let elapsed = self.chunk_at.elapsed();
+        if elapsed < self.interval {
+            return;
+        }
+        if let Sink::Flow { client, index } = &self.sink {
+            let index = *index;
+            client
+                .clone()
+                .lock()
+                .expect("meter poisoned")
+                .merge(self, index);
+            self.chunk_at = Instant::now();
+            return;
+        }
+
+        let cpu = self.cpu.lap();
+        let sample = self.sample(
+            SampleKind::Interval,
+            self.bytes,
+            self.packets,
+            elapsed,
+            cpu,
+            &self.latency,
+        );
+        self.write(&sample);
+        self.rates
+            .push(self.bytes as f64 / elapsed.as_secs_f64() / 1e6);
+        self.roll_over();
+        self.chunk_at = Instant::now();
+    }
+
+    /// Take over what the `index`th flow recorded since it last handed
+    /// over, and clear it there.
+    fn merge(&mut self, flow: &mut Meter, index: usize) {
+        if self.warming_up {
+            // Flows only hand over what they measured after their warmup.
+            self.warming_up = false;
+            self.restart(Instant::now());
+        }
+        self.bytes += flow.bytes;
+        self.flows[index].bytes += flow.bytes;
+        self.flows[index]
+            .latency
+            .add(&flow.latency)
+            .expect("histograms share bounds");
+        self.packets += flow.packets;
+        self.latency
+            .add(&flow.latency)
+            .expect("histograms share bounds");
+        if let Some(integrity) = flow.integrity.take() {
+            self.integrity
+                .get_or_insert_with(Integrity::default)
+                .add(&integrity);
+            self.total_integrity.get_or_insert_with(Integrity::default);
+            flow.integrity = Some(Integrity::default());
+        }
+        flow.bytes = 0;
+        flow.packets = 0;
+        flow.latency.reset();
+        self.lap();
+    }
+
+    /// Report the whole run, including the unfinished interval and how the
+    /// connections, and the streams of each, shared it. A flow only hands
+    /// over the rest, the client reports once every flow is done.
+    pub fn finish(&mut self) {
+        if let Sink::Flow { client, index } = &self.sink {
+            let index = *index;
+            client
+                .clone()
+                .lock()
+                .expect("meter poisoned")
+                .merge(self, index);
+            return;
+        }
+        self.roll_over();
+        self.cpu.lap();
+        let elapsed = self.started_at.elapsed();
+        let mut connections: Vec<_> = (0..self.connections).map(FlowTotal::new).collect();
+        for flow in &self.flows {
+            let connection = &mut connections[flow.connection];
+            connection.bytes += flow.bytes;
+            connection
+                .latency
+                .add(&flow.latency)
+                .expect("histograms share bounds");
+        }
+        // Streams only when some connection had several.
+        let streams = if self.flows.len() > self.connections {
+            fairness(&self.flows, elapsed)
+        } else {
+            None
+        };
+        let sample = Sample {
+            spread: Spread::of(&self.rates),
+            connections: fairness(&connections, elapsed),
+            streams,
+            ..self.sample(
+                SampleKind::Total,
+                self.total_bytes,
+                self.total_packets,
+                elapsed,
+                self.cpu.total(),
+                &self.total_latency,
+            )
+        };
+        self.write(&sample);
+    }
+
+    /// Move the current interval into the totals.
+    fn roll_over(&mut self) {
+        self.total_bytes += self.bytes;
+        self.total_packets += self.packets;
+        self.total_latency
+            .add(&self.latency)
+            .expect("histograms share bounds");
+        self.latency.reset();
+        self.bytes = 0;
+        self.packets = 0;
+        if let (Some(total), Some(integrity)) = (&mut self.total_integrity, &mut self.integrity) {
+            total.add(integrity);
+            *integrity = Integrity::default();
+        }
+    }
+
+    fn sample(
+        &self,
+        kind: SampleKind,
+        bytes: u64,
+        packets: u64,
+        elapsed: Duration,
+        cpu: Duration,
+        latency: &Histogram<u64>,
+    ) -> Sample {
+        let us = |q: f64| latency.value_at_quantile(q) / 1000;
+        Sample {
+            bytes,
+            packets,
+            interval_ms: elapsed.as_millis() as u64,
+            p50_us: us(0.5),
+            p90_us: us(0.9),
+            p99_us: us(0.99),
+            p999_us: us(0.999),
+            max_us: latency.max() / 1000,
+            cpu_us: cpu.as_micros() as u64,
+            bytes_per_cpu_s: per_cpu_second(bytes, cpu),
+            integrity: match kind {
+                SampleKind::Interval => self.integrity,
+                SampleKind::Total => self.total_integrity,
+            },
+            ..self.reporter().sample(kind)
+        }
+    }
+
+    fn reporter(&self) -> &Reporter {
+        match &self.sink {
+            Sink::Report(reporter) => reporter,
+            Sink::Flow { .. } => unreachable!("flows hand their intervals over"),
+        }
+    }
+
+    fn write(&mut self, sample: &Sample) {
+        match &mut self.sink {
+            Sink::Report(reporter) => reporter.write(sample),
+            Sink::Flow { .. } => unreachable!("flows hand their intervals over"),
+        }
+    }
+}
+
+fn histogram() -> Histogram<u64> {
+    Histogram::new_with_bounds(1, MAX_RTT.as_nanos() as u64, 3).expect("valid histogram bounds")
+}
+
+/// How `flows` shared the throughput over `elapsed`, and how their round
+/// trips suffered.
+fn fairness(flows: &[FlowTotal], elapsed: Duration) -> Option<Fairness> {
+    let rates: Vec<_> = flows
+        .iter()
+        .map(|f| f.bytes as f64 / elapsed.as_secs_f64() / 1e6)
+        .collect();
+    let p99_us: Vec<_> = flows
+        .iter()
+        .map(|f| f.latency.value_at_quantile(0.99) / 1000)
+        .collect();
+    Fairness::of(&rates, &p99_us)
+}
+
+fn per_cpu_second(bytes: u64, cpu: Duration) -> u64 {
+    // In u128, the bytes times a million overflow u64 past 18 TB.
+    match cpu.as_micros() {
+        0 => 0,
+        us => (bytes as u128 * 1_000_000 / us) as u64,
+    }
+}
+
+/// Traffic echoed by every connection of a server.
+#[derive(Debug, Default)]
+pub struct Echoed {
+    bytes: AtomicU64,
+    packets: AtomicU64,
+}
+
+impl Echoed {
+    pub fn add(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn take(&self) -> (u64, u64) {
+        (
+            self.bytes.swap(0, Ordering::Relaxed),
+            self.packets.swap(0, Ordering::Relaxed),
+        )
+    }
+}
+
+/// Server counterpart of [`Meter`], reports what its connections echoed
+/// and the CPU it took, from a thread of its own.
+pub struct ServerMeter {
+    reporter: Reporter,
+    echoed: Arc<Echoed>,
+}
+
+impl ServerMeter {
+    pub fn new(reporter: Reporter) -> Self {
+        Self {
+            reporter,
+            echoed: Arc::default(),
+        }
+    }
+
+    /// Counter for the connections to add to.
+    pub fn echoed(&self) -> Arc<Echoed> {
+        self.echoed.clone()
+    }
+
+    /// Report every interval in which something was echoed, forever.
+    pub fn run(mut self) {
+        let mut cpu = CpuClock::new();
+        let mut chunk_at = Instant::now();
+        loop {
+            std::thread::sleep(INTERVAL);
+            let elapsed = chunk_at.elapsed();
+            chunk_at = Instant::now();
+            let cpu = cpu.lap();
+            let (bytes, packets) = self.echoed.take();
+            if bytes == 0 {
+                continue;
+            }
+            let sample = Sample {
+                bytes,
+                packets,
+                interval_ms: elapsed.as_millis() as u64,
+                cpu_us: cpu.as_micros() as u64,
+                bytes_per_cpu_s: per_cpu_second(bytes, cpu),
+                ..self.reporter.sample(SampleKind::Interval)
+            };
+            self.reporter.write(&sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::report::tests::{reporter, Captured};
+    use crate::report::Format;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn meter() -> (Meter, Captured) {
+        let (reporter, out) = reporter(Format::Json);
+        (Meter::new(reporter), out)
+    }
+
+    /// Whether `us` is `expected` to the histogram's three significant
+    /// digits.
+    fn near(us: u64, expected: u64) -> bool {
+        us.abs_diff(expected) <= expected / 1000 + 1
+    }
+
+    /// The total the meter reported last.
+    fn total(out: &Captured) -> Sample {
+        let sample = out.samples().pop().expect("a total");
+        assert_eq!(sample.kind, SampleKind::Total);
+        sample
+    }
+
+    #[test]
+    fn reports_percentiles_of_known_rtts() {
+        let (mut meter, out) = meter();
+        meter.start();
+        // A round trip of each microsecond up to a millisecond, shuffled.
+        for i in 0..1000 {
+            let us = (i * 7919) % 1000 + 1;
+            meter.record(100, Duration::from_micros(us));
+        }
+        meter.finish();
+
+        let total = total(&out);
+        assert_eq!((total.bytes, total.packets), (100_000, 1000));
+        let percentiles = [
+            total.p50_us,
+            total.p90_us,
+            total.p99_us,
+            total.p999_us,
+            total.max_us,
+        ];
+        for (us, expected) in percentiles.into_iter().zip([500, 900, 990, 999, 1000]) {
+            assert!(near(us, expected), "{:?}", percentiles);
+        }
+    }
+
+    #[test]
+    fn merges_flows_into_the_totals() {
+        let (client, out) = meter();
+        let shared = Arc::new(Mutex::new(client));
+        let mut a = Meter::flow(&shared);
+        let mut b = a.stream();
+        let mut c = Meter::flow(&shared);
+        for meter in [&mut a, &mut b, &mut c] {
+            meter.start();
+        }
+        for _ in 0..100 {
+            a.record(1000, MS);
+            b.record(1000, 2 * MS);
+        }
+        for _ in 0..199 {
+            c.record(500, 4 * MS);
+        }
+        c.record(500, 10 * MS);
+        for meter in [&mut a, &mut b, &mut c] {
+            meter.finish();
+        }
+        shared.lock().unwrap().finish();
+
+        let total = total(&out);
+        assert_eq!((total.bytes, total.packets), (300_000, 400));
+        assert!(near(total.p50_us, 2000), "{}", total.p50_us);
+        assert!(near(total.p90_us, 4000), "{}", total.p90_us);
+        assert!(near(total.max_us, 10_000), "{}", total.max_us);
+
+        // The first connection carried twice what the second did.
+        let connections = total.connections.expect("two connections");
+        assert_eq!(connections.connections, 2);
+        let [first, second] = connections.rates[..] else {
+            panic!("{:?}", connections.rates);
+        };
+        assert!((first / second - 2.0).abs() < 1e-9);
+        assert!((connections.index - 0.9).abs() < 1e-9);
+
+        let streams = total.streams.expect("three streams");
+        assert_eq!(streams.connections, 3);
+        assert!((streams.index - 1.0).abs() < 1e-9);
+        for (&p99, expected) in streams.p99_us.iter().zip([1000, 2000, 4000]) {
+            assert!(near(p99, expected), "{:?}", streams.p99_us);
+        }
+    }
+
+    #[test]
+    fn discards_the_warmup() {
+        let (meter, out) = meter();
+        let mut meter = meter.with_warmup(40 * MS);
+        meter.track_integrity();
+        meter.start();
+        for _ in 0..5 {
+            meter.count(Check::Corrupt);
+            meter.record(1000, 50 * MS);
+        }
+        meter.lost(3);
+        thread::sleep(50 * MS);
+
+        // Losses and checks count once the warmup is over, even before the
+        // first round trip after it.
+        meter.lost(2);
+        meter.count(Check::Duplicated);
+        for _ in 0..3 {
+            meter.record(1000, MS);
+        }
+        meter.finish();
+
+        let total = total(&out);
+        assert_eq!((total.bytes, total.packets), (3000, 3));
+        assert!(near(total.max_us, 1000), "{}", total.max_us);
+        let integrity = Integrity {
+            duplicated: 1,
+            lost: 2,
+            ..Integrity::default()
+        };
+        assert_eq!(total.integrity, Some(integrity));
+    }
+
+    #[test]
+    fn is_done_after_the_warmup_and_duration() {
+        let (mut endless, _) = meter();
+        endless.start();
+        let (meter, _) = meter();
+        let mut meter = meter.with_warmup(50 * MS).with_duration(100 * MS);
+        meter.start();
+        assert!(!meter.is_done());
+        thread::sleep(75 * MS);
+        assert!(!meter.is_done());
+        thread::sleep(100 * MS);
+        assert!(meter.is_done());
+        assert!(!endless.is_done());
+    }
+
+    #[test]
+    fn spreads_throughput_across_intervals() {
+        let (reporter, out) = reporter(Format::Json);
+        let mut meter = Meter::with_sink(Sink::Report(reporter), 20 * MS);
+        meter.start();
+        // Each round trip ends an interval of its own.
+        for i in 1..=5 {
+            thread::sleep(25 * MS);
+            meter.record(i * 10_000, MS);
+        }
+        meter.finish();
+
+        let samples = out.samples();
+        let rates: Vec<_> = samples
+            .iter()
+            .filter(|s| s.kind == SampleKind::Interval)
+            .map(|s| s.bytes as f64 / s.interval_ms as f64 / 1e3)
+            .collect();
+        assert_eq!(rates.len(), 5);
+        let expected = Spread::of(&rates).unwrap();
+        let spread = total(&out).spread.expect("full intervals");
+        assert_eq!(spread.intervals, 5);
+        // Interval samples round to the millisecond.
+        let close = |a: f64, b: f64| (a - b).abs() <= 0.05 * b;
+        assert!(close(spread.mean, expected.mean), "{:?}", spread);
+        assert!(close(spread.stddev, expected.stddev), "{:?}", spread);
+        assert!(close(spread.min, expected.min), "{:?}", spread);
+        assert!(close(spread.max, expected.max), "{:?}", spread);
+        assert!(spread.min < spread.mean && spread.mean < spread.max);
+    }
+}
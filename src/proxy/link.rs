@@ -0,0 +1,447 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::Args;
+
+use super::trace::{Replay, Trace};
+
+/// How the relay impairs traffic, the same way in each direction.
+#[derive(Args, Debug, Clone)]
+pub struct ImpairOpt {
+    /// One-way delay added to every packet, in milliseconds.
+    #[clap(long, default_value = "0", value_name = "MS", value_parser = millis)]
+    pub delay: Duration,
+
+    /// Delay varies uniformly by up to this many milliseconds either way.
+    #[clap(long, default_value = "0", value_name = "MS", value_parser = millis)]
+    pub jitter: Duration,
+
+    /// Percentage of packets dropped.
+    #[clap(long, default_value = "0", value_name = "PCT", value_parser = percent)]
+    pub loss: f64,
+
+    /// Mean length of a run of lost packets, above 1 for bursty loss.
+    #[clap(long, default_value = "1", value_name = "PACKETS", value_parser = burst)]
+    pub loss_burst: f64,
+
+    /// Percentage of packets sent without the delay, ahead of those queued
+    /// before them.
+    #[clap(long, default_value = "0", value_name = "PCT", value_parser = percent)]
+    pub reorder: f64,
+
+    /// Percentage of packets sent twice.
+    #[clap(long, default_value = "0", value_name = "PCT", value_parser = percent)]
+    pub duplicate: f64,
+
+    /// Bandwidth cap in Mbit/s.
+    #[clap(long, value_name = "MBIT", value_parser = mbit)]
+    pub rate: Option<f64>,
+
+    /// Replay a link trace instead of a fixed rate: Mahimahi delivery
+    /// opportunities, one millisecond timestamp per line, or CSV rows of
+    /// `time_ms,rate_mbit,rtt_ms`.
+    #[clap(long, value_name = "FILE", conflicts_with = "rate")]
+    pub trace: Option<PathBuf>,
+
+    /// Trace from server to client, `--trace` otherwise.
+    #[clap(long, value_name = "FILE", conflicts_with = "rate")]
+    pub downlink_trace: Option<PathBuf>,
+
+    /// Packets waiting for a capped link before new ones are dropped.
+    #[clap(long, default_value = "1000", value_name = "PACKETS")]
+    pub queue: usize,
+
+    /// Seed of the random impairments, different every run otherwise.
+    #[clap(long)]
+    pub seed: Option<u64>,
+}
+
+impl ImpairOpt {
+    /// Whether any impairment is configured.
+    pub fn is_active(&self) -> bool {
+        !self.delay.is_zero()
+            || !self.jitter.is_zero()
+            || self.loss > 0.0
+            || self.reorder > 0.0
+            || self.duplicate > 0.0
+            || self.rate.is_some()
+            || self.trace.is_some()
+            || self.downlink_trace.is_some()
+    }
+
+    /// Command line for a `bench proxy` process impairing the same way.
+    pub(crate) fn args(&self) -> Vec<String> {
+        let millis = |d: Duration| (d.as_secs_f64() * 1000.0).to_string();
+        let mut args = vec![
+            "--delay".to_string(),
+            millis(self.delay),
+            "--jitter".to_string(),
+            millis(self.jitter),
+            "--loss".to_string(),
+            self.loss.to_string(),
+            "--loss-burst".to_string(),
+            self.loss_burst.to_string(),
+            "--reorder".to_string(),
+            self.reorder.to_string(),
+            "--duplicate".to_string(),
+            self.duplicate.to_string(),
+            "--queue".to_string(),
+            self.queue.to_string(),
+        ];
+        if let Some(rate) = self.rate {
+            args.extend(["--rate".to_string(), rate.to_string()]);
+        }
+        if let Some(path) = &self.trace {
+            args.extend(["--trace".to_string(), path.display().to_string()]);
+        }
+        if let Some(path) = &self.downlink_trace {
+            args.extend(["--downlink-trace".to_string(), path.display().to_string()]);
+        }
+        if let Some(seed) = self.seed {
+            args.extend(["--seed".to_string(), seed.to_string()]);
+        }
+        args
+    }
+
+    /// Traces from client to server and back.
+    pub(crate) fn traces(&self) -> io::Result<(Option<Trace>, Option<Trace>)> {
+        let up = self.trace.as_deref().map(Trace::load).transpose()?;
+        let down = match &self.downlink_trace {
+            Some(path) => Some(Trace::load(path)?),
+            None => up.clone(),
+        };
+        Ok((up, down))
+    }
+
+    /// Byte streams cannot lose, reorder or duplicate part of themselves.
+    pub(crate) fn check_stream(&self) -> io::Result<()> {
+        if self.loss > 0.0 || self.reorder > 0.0 || self.duplicate > 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "loss, reordering and duplication only apply to datagrams",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn millis(s: &str) -> Result<Duration, String> {
+    let ms: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    Duration::try_from_secs_f64(ms / 1000.0).map_err(|e| format!("{}", e))
+}
+
+fn percent(s: &str) -> Result<f64, String> {
+    let pct: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    match (0.0..=100.0).contains(&pct) {
+        true => Ok(pct),
+        false => Err("not between 0 and 100".to_string()),
+    }
+}
+
+fn burst(s: &str) -> Result<f64, String> {
+    let packets: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    match packets.is_finite() && packets >= 1.0 {
+        true => Ok(packets),
+        false => Err("not a number of at least 1".to_string()),
+    }
+}
+
+fn mbit(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    // Slower links would hold a packet for longer than an `Instant` reaches.
+    match rate.is_finite() && rate >= 0.001 {
+        true => Ok(rate),
+        false => Err("not a number of at least 0.001".to_string()),
+    }
+}
+
+/// One direction of the emulated path.
+///
+/// Packets are serialized at the capped rate, or as a trace allows, through a
+/// queue of bounded length, then delayed. Loss follows a Gilbert model, whose
+/// bad state lasts `loss_burst` packets on average and drops everything.
+pub struct Link {
+    opt: ImpairOpt,
+    /// Keep packets in order, none lost or duplicated, for byte streams.
+    ordered: bool,
+    rng: Rng,
+    bursting: bool,
+    replay: Option<Replay>,
+    /// When the capped link finishes sending what is queued.
+    free_at: Instant,
+    /// Departure of every packet waiting for the capped link.
+    queued: VecDeque<Instant>,
+    last_arrival: Instant,
+}
+
+impl Link {
+    pub fn new(opt: &ImpairOpt, ordered: bool, seed: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            opt: opt.clone(),
+            ordered,
+            rng: Rng::new(seed),
+            bursting: false,
+            replay: None,
+            free_at: now,
+            queued: VecDeque::new(),
+            last_arrival: now,
+        }
+    }
+
+    /// Shape the link by `trace`, replayed from now on.
+    pub fn with_trace(mut self, trace: Option<Trace>) -> Self {
+        self.replay = trace.map(|trace| Replay::new(trace, Instant::now()));
+        self
+    }
+
+    /// Pass a packet of `len` bytes arriving `now`, pushing when each copy
+    /// comes out the other end. Nothing is pushed for a dropped packet, and
+    /// ordered links push exactly one.
+    pub fn send(&mut self, now: Instant, len: usize, out: &mut Vec<Instant>) {
+        if !self.ordered && self.lose() {
+            return;
+        }
+        let copies = match !self.ordered && self.rng.chance(self.opt.duplicate) {
+            true => 2,
+            false => 1,
+        };
+        for _ in 0..copies {
+            let Some(departure) = self.serialize(now, len) else {
+                return;
+            };
+            let mut arrival = match !self.ordered && self.rng.chance(self.opt.reorder) {
+                true => departure,
+                false => departure + self.delay(departure),
+            };
+            if self.ordered {
+                arrival = arrival.max(self.last_arrival);
+                self.last_arrival = arrival;
+            }
+            out.push(arrival);
+        }
+    }
+
+    fn lose(&mut self) -> bool {
+        if self.opt.loss_burst <= 1.0 {
+            return self.rng.chance(self.opt.loss);
+        }
+        let loss = self.opt.loss / 100.0;
+        // Leaving the bad state after `loss_burst` packets on average, and
+        // entering it often enough to lose `loss` of all packets.
+        let leave = 1.0 / self.opt.loss_burst;
+        let enter = loss * leave / (1.0 - loss).max(f64::EPSILON);
+        self.bursting = match self.bursting {
+            true => self.rng.unit() >= leave,
+            false => self.rng.unit() < enter,
+        };
+        self.bursting
+    }
+
+    /// When the packet has been sent on the capped link, `None` when the
+    /// queue is full.
+    fn serialize(&mut self, now: Instant, len: usize) -> Option<Instant> {
+        if self.opt.rate.is_none() && self.replay.is_none() {
+            return Some(now);
+        }
+        while self.queued.front().is_some_and(|&at| at <= now) {
+            self.queued.pop_front();
+        }
+        // Streams are held back by the relay instead of dropping.
+        if !self.ordered && self.queued.len() >= self.opt.queue {
+            return None;
+        }
+        self.free_at = match (&mut self.replay, self.opt.rate) {
+            (Some(replay), _) => replay.serialize(now, self.free_at, len),
+            (None, Some(rate)) => {
+                let start = self.free_at.max(now);
+                start + Duration::from_secs_f64(len as f64 * 8.0 / (rate * 1e6))
+            }
+            (None, None) => unreachable!("checked above"),
+        };
+        self.queued.push_back(self.free_at);
+        Some(self.free_at)
+    }
+
+    /// Propagation delay of a packet sent `at`.
+    fn delay(&mut self, at: Instant) -> Duration {
+        let jitter = self.opt.jitter.as_secs_f64() * (2.0 * self.rng.unit() - 1.0);
+        let delay = self.opt.delay.as_secs_f64() + jitter;
+        let traced = self.replay.as_ref().map_or(Duration::ZERO, |r| r.delay(at));
+        Duration::from_secs_f64(delay.max(0.0)) + traced
+    }
+}
+
+/// Seed for a link, from the options or the clock.
+pub(crate) fn seed(opt: &ImpairOpt) -> u64 {
+    opt.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+    })
+}
+
+/// splitmix64, plenty for picking packets.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, percent: f64) -> bool {
+        percent > 0.0 && self.unit() * 100.0 < percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[derive(Parser)]
+    struct Cli {
+        #[clap(flatten)]
+        impair: ImpairOpt,
+    }
+
+    fn link(args: &[&str], ordered: bool) -> Link {
+        let cli = Cli::parse_from(["relay"].iter().chain(args));
+        Link::new(&cli.impair, ordered, 42)
+    }
+
+    /// Send `n` packets at once, returning when each one arrives, `None`
+    /// for the dropped ones.
+    fn send(link: &mut Link, now: Instant, n: usize, len: usize) -> Vec<Option<Instant>> {
+        let mut out = Vec::new();
+        (0..n)
+            .map(|_| {
+                out.clear();
+                link.send(now, len, &mut out);
+                out.first().copied()
+            })
+            .collect()
+    }
+
+    /// Share of lost packets and mean length of their runs.
+    fn losses(args: &[&str]) -> (f64, f64) {
+        let arrivals = send(&mut link(args, false), Instant::now(), 200_000, 100);
+        let lost = arrivals.iter().filter(|a| a.is_none()).count();
+        let runs = arrivals
+            .windows(2)
+            .filter(|w| w[0].is_some() && w[1].is_none())
+            .count();
+        (
+            lost as f64 / arrivals.len() as f64,
+            lost as f64 / runs as f64,
+        )
+    }
+
+    #[test]
+    fn loses_the_configured_share() {
+        let (rate, burst) = losses(&["--loss", "10"]);
+        assert!((rate - 0.10).abs() < 0.005, "{}", rate);
+        assert!((burst - 1.0 / 0.9).abs() < 0.05, "{}", burst);
+
+        let (rate, burst) = losses(&["--loss", "10", "--loss-burst", "4"]);
+        assert!((rate - 0.10).abs() < 0.01, "{}", rate);
+        assert!((burst - 4.0).abs() < 0.2, "{}", burst);
+    }
+
+    #[test]
+    fn serializes_at_the_capped_rate() {
+        // 1000 bytes take a millisecond at 8 Mbit/s.
+        let mut link = link(&["--rate", "8", "--delay", "5"], false);
+        let now = Instant::now();
+        let arrivals = send(&mut link, now, 3, 1000);
+        let after: Vec<_> = arrivals.iter().map(|a| a.unwrap() - now).collect();
+        assert_eq!(after, [6 * MS, 7 * MS, 8 * MS]);
+
+        // The link was idle meanwhile.
+        let later = now + 100 * MS;
+        assert_eq!(send(&mut link, later, 1, 1000)[0], Some(later + 6 * MS));
+    }
+
+    #[test]
+    fn drops_what_overflows_the_queue() {
+        let mut link = link(&["--rate", "8", "--queue", "5"], false);
+        let now = Instant::now();
+        let arrivals = send(&mut link, now, 8, 1000);
+        assert_eq!(arrivals.iter().filter(|a| a.is_some()).count(), 5);
+        assert!(arrivals[5..].iter().all(Option::is_none));
+
+        // Room again once the first packets are out.
+        let arrivals = send(&mut link, now + 2 * MS, 3, 1000);
+        assert_eq!(arrivals.iter().filter(|a| a.is_some()).count(), 2);
+    }
+
+    #[test]
+    fn duplicates_and_reorders() {
+        let mut duplicating = link(&["--duplicate", "100", "--delay", "10"], false);
+        let now = Instant::now();
+        let mut out = Vec::new();
+        duplicating.send(now, 100, &mut out);
+        assert_eq!(out, [now + 10 * MS; 2]);
+
+        // Reordered packets skip the delay.
+        let mut reordering = link(&["--reorder", "50", "--delay", "10"], false);
+        let arrivals = send(&mut reordering, now, 10_000, 100);
+        let early = arrivals.iter().filter(|&&a| a == Some(now)).count();
+        assert!((4_500..5_500).contains(&early), "{}", early);
+        assert!(arrivals
+            .iter()
+            .all(|&a| a == Some(now) || a == Some(now + 10 * MS)));
+    }
+
+    #[test]
+    fn ordered_links_neither_drop_nor_duplicate() {
+        let args = [
+            "--loss",
+            "50",
+            "--loss-burst",
+            "3",
+            "--duplicate",
+            "50",
+            "--reorder",
+            "50",
+            "--jitter",
+            "5",
+            "--delay",
+            "10",
+            "--rate",
+            "8",
+            "--queue",
+            "1",
+        ];
+        let mut link = link(&args, true);
+        let now = Instant::now();
+        let mut last = now;
+        let mut out = Vec::new();
+        for i in 0..1000 {
+            out.clear();
+            link.send(now + i * MS / 2, 1000, &mut out);
+            assert_eq!(out.len(), 1);
+            assert!(out[0] >= last);
+            last = out[0];
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! `bench proxy`: a relay between client and server that impairs the traffic
+//! passing through it, to benchmark under WAN-like conditions on one machine
+//! without tc/netem.
+//!
+//! QUIC and plain UDP are relayed datagram by datagram, so every impairment
+//! applies. TCP is relayed as a byte stream, over connections of the relay's
+//! own, which can only be delayed and paced.
+
+mod link;
+mod tcp;
+mod trace;
+mod udp;
+
+use std::io;
+use std::net::SocketAddr;
+
+use clap::Args;
+
+pub use self::link::ImpairOpt;
+use self::link::Link;
+use self::tcp::TcpRelay;
+use self::udp::UdpRelay;
+use crate::bench::Proto;
+
+#[derive(Args, Debug, Clone)]
+pub struct ProxyOpt {
+    /// Protocol relayed, TCP as a stream and everything else as datagrams.
+    #[clap(long, value_enum)]
+    pub proto: Proto,
+
+    /// Address clients connect to.
+    #[clap(short, long, default_value = "127.0.0.1:0", value_name = "ADDR")]
+    pub listen: SocketAddr,
+
+    /// Server's address.
+    #[clap(long, value_name = "ADDR")]
+    pub forward_to: SocketAddr,
+
+    #[clap(flatten)]
+    pub impair: ImpairOpt,
+}
+
+/// Relay until killed.
+pub fn run(opt: &ProxyOpt) -> io::Result<()> {
+    let relay = Relay::bind(opt.proto, opt.listen, opt.forward_to, &opt.impair)?;
+    println!("listening on {}", relay.local_addr()?);
+    relay.run()
+}
+
+enum Relay {
+    Tcp(TcpRelay),
+    Udp(Box<UdpRelay>),
+}
+
+impl Relay {
+    fn bind(
+        proto: Proto,
+        listen: SocketAddr,
+        server: SocketAddr,
+        impair: &ImpairOpt,
+    ) -> io::Result<Self> {
+        let seed = link::seed(impair);
+        let (up_trace, down_trace) = impair.traces()?;
+        match proto {
+            Proto::Tcp | Proto::Tls => {
+                impair.check_stream()?;
+                let up = Link::new(impair, true, seed).with_trace(up_trace);
+                let down = Link::new(impair, true, !seed).with_trace(down_trace);
+                TcpRelay::bind(listen, server, up, down).map(Relay::Tcp)
+            }
+            Proto::Udp | Proto::Quinn | Proto::Tquic => {
+                let up = Link::new(impair, false, seed).with_trace(up_trace);
+                let down = Link::new(impair, false, !seed).with_trace(down_trace);
+                UdpRelay::bind(listen, server, up, down).map(|relay| Relay::Udp(Box::new(relay)))
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Relay::Tcp(relay) => relay.local_addr(),
+            Relay::Udp(relay) => relay.local_addr(),
+        }
+    }
+
+    fn run(self) -> io::Result<()> {
+        match self {
+            Relay::Tcp(relay) => relay.run(),
+            Relay::Udp(relay) => relay.run(),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use log::{debug, error};
+
+use super::link::Link;
+
+/// Bytes read from a socket at a time, each read goes through the link as
+/// one packet.
+const CHUNK: usize = 1 << 16;
+
+/// Chunks held per direction before the relay stops reading, the window of
+/// the relay's own connections.
+const BACKLOG: usize = 1024;
+
+/// Relays TCP connections to `server`, delaying and pacing the bytes of each
+/// direction through a link shared by all connections. Each direction of a
+/// connection takes a reading and a writing thread.
+pub(crate) struct TcpRelay {
+    listener: TcpListener,
+    server: SocketAddr,
+    up: Arc<Mutex<Link>>,
+    down: Arc<Mutex<Link>>,
+}
+
+impl TcpRelay {
+    pub(crate) fn bind(
+        listen: SocketAddr,
+        server: SocketAddr,
+        up: Link,
+        down: Link,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(listen)?,
+            server,
+            up: Arc::new(Mutex::new(up)),
+            down: Arc::new(Mutex::new(down)),
+        })
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Relay forever.
+    pub(crate) fn run(self) -> io::Result<()> {
+        loop {
+            let (client, from) = self.listener.accept()?;
+            let upstream = match TcpStream::connect(self.server) {
+                Ok(upstream) => upstream,
+                Err(e) => {
+                    error!("relay to {} failed: {}", self.server, e);
+                    continue;
+                }
+            };
+            debug!("relaying {} to {}", from, self.server);
+            client.set_nodelay(true)?;
+            upstream.set_nodelay(true)?;
+            pipe(client.try_clone()?, upstream.try_clone()?, self.up.clone());
+            pipe(upstream, client, self.down.clone());
+        }
+    }
+}
+
+/// Copy `from` to `to` through `link`, then pass on the end of the stream.
+fn pipe(mut from: TcpStream, to: TcpStream, link: Arc<Mutex<Link>>) {
+    let (tx, rx) = mpsc::sync_channel(BACKLOG);
+    thread::spawn(move || write_due(to, rx));
+    thread::spawn(move || {
+        let mut buf = vec![0; CHUNK];
+        let mut arrivals = Vec::with_capacity(1);
+        loop {
+            let n = match from.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            arrivals.clear();
+            link.lock()
+                .expect("link poisoned")
+                .send(Instant::now(), n, &mut arrivals);
+            let &[arrival] = arrivals.as_slice() else {
+                error!("relay link lost or duplicated part of a stream");
+                break;
+            };
+            if tx.send((arrival, buf[..n].to_vec())).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn write_due(mut to: TcpStream, rx: Receiver<(Instant, Vec<u8>)>) {
+    for (at, chunk) in rx {
+        thread::sleep(at.saturating_duration_since(Instant::now()));
+        if let Err(e) = to.write_all(&chunk) {
+            debug!("relay write failed: {}", e);
+            return;
+        }
+    }
+    let _ = to.shutdown(Shutdown::Write);
+}
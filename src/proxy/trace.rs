@@ -0,0 +1,303 @@
+//! Recorded link conditions the relay replays, from the moment it starts.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Bytes a Mahimahi delivery opportunity carries.
+const MTU: usize = 1500;
+
+#[derive(Debug, Clone)]
+pub enum Trace {
+    /// Mahimahi: one millisecond timestamp per line, each an opportunity to
+    /// deliver one MTU. Repeats once the last timestamp is reached.
+    Opportunities { times: Vec<u64>, period: u64 },
+    /// CSV rows of `time_ms,rate_mbit,rtt_ms`, each holding until the next
+    /// one. The last row holds forever.
+    Schedule(Vec<Step>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    at_ms: u64,
+    rate_mbit: f64,
+    rtt: Duration,
+}
+
+impl Trace {
+    /// Read a trace, CSV when its first line has a comma.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid line {:?}", line),
+            )
+        };
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .peekable();
+        let csv = lines.peek().is_some_and(|l| l.contains(','));
+
+        let trace = if csv {
+            let mut steps = Vec::new();
+            for line in lines {
+                let fields: Vec<_> = line.split(',').map(str::trim).collect();
+                let [at, rate, rtt] = fields[..] else {
+                    return Err(invalid(line));
+                };
+                let (Ok(at_ms), Ok(rate_mbit), Ok(rtt_ms)) =
+                    (at.parse(), rate.parse(), rtt.parse::<f64>())
+                else {
+                    // A header row.
+                    if steps.is_empty() && at.parse::<f64>().is_err() {
+                        continue;
+                    }
+                    return Err(invalid(line));
+                };
+                steps.push(Step {
+                    at_ms,
+                    rate_mbit,
+                    rtt: Duration::try_from_secs_f64(rtt_ms / 1000.0).map_err(|_| invalid(line))?,
+                });
+            }
+            Trace::Schedule(steps)
+        } else {
+            let times = lines
+                .map(|l| l.parse().map_err(|_| invalid(l)))
+                .collect::<io::Result<Vec<u64>>>()?;
+            let period = times.last().copied().unwrap_or(0).max(1);
+            Trace::Opportunities { times, period }
+        };
+        // Lookups bisect the timestamps.
+        let sorted = match &trace {
+            Trace::Opportunities { times, .. } => times.is_sorted(),
+            Trace::Schedule(steps) => steps.is_sorted_by_key(|s| s.at_ms),
+        };
+        let error = match &trace {
+            _ if !sorted => "timestamps go back in time",
+            Trace::Opportunities { times, .. } if times.is_empty() => "empty trace",
+            Trace::Schedule(steps) if steps.is_empty() => "empty trace",
+            Trace::Schedule(steps) if steps[steps.len() - 1].rate_mbit <= 0.0 => {
+                "the last row needs a rate, it holds forever"
+            }
+            _ => return Ok(trace),
+        };
+        Err(io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+/// A trace being replayed by one direction of a link.
+#[derive(Debug)]
+pub struct Replay {
+    trace: Trace,
+    epoch: Instant,
+    cursor: Cursor,
+}
+
+/// Where a replay of delivery opportunities stands.
+#[derive(Debug, Default)]
+struct Cursor {
+    /// Next unused opportunity, counting across repetitions.
+    next: u64,
+    /// Bytes left in the last used opportunity.
+    credit: usize,
+}
+
+impl Replay {
+    pub fn new(trace: Trace, epoch: Instant) -> Self {
+        Self {
+            trace,
+            epoch,
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// When a packet of `len` bytes queued at `now` has been sent, with the
+    /// link busy until `free_at`.
+    pub fn serialize(&mut self, now: Instant, free_at: Instant, len: usize) -> Instant {
+        let start = free_at.max(now);
+        match &self.trace {
+            Trace::Opportunities { times, period } => {
+                let at = |idx: u64| {
+                    let n = times.len() as u64;
+                    let ms = idx / n * period + times[(idx % n) as usize];
+                    self.epoch + Duration::from_millis(ms)
+                };
+                let first = || first_at(times, *period, now.saturating_duration_since(self.epoch));
+                self.cursor.deliver(at, first, now, free_at, len)
+            }
+            Trace::Schedule(steps) => {
+                let mut start = start;
+                let mut step = self.step(steps, start);
+                // A rate of zero is an outage, waiting for the next step.
+                while step.rate_mbit <= 0.0 {
+                    start = self.epoch + Duration::from_millis(self.next_step(steps, start).at_ms);
+                    step = self.step(steps, start);
+                }
+                start + Duration::from_secs_f64(len as f64 * 8.0 / (step.rate_mbit * 1e6))
+            }
+        }
+    }
+
+    /// Delay on top of the configured one, half the round trip of a schedule.
+    pub fn delay(&self, at: Instant) -> Duration {
+        match &self.trace {
+            Trace::Opportunities { .. } => Duration::ZERO,
+            Trace::Schedule(steps) => self.step(steps, at).rtt / 2,
+        }
+    }
+
+    fn step(&self, steps: &[Step], at: Instant) -> Step {
+        let ms = at.saturating_duration_since(self.epoch).as_millis() as u64;
+        let i = steps.partition_point(|s| s.at_ms <= ms);
+        steps[i.saturating_sub(1)]
+    }
+
+    /// The step after the one at `at`, there is one after every outage.
+    fn next_step(&self, steps: &[Step], at: Instant) -> Step {
+        let ms = at.saturating_duration_since(self.epoch).as_millis() as u64;
+        steps[steps.partition_point(|s| s.at_ms <= ms)]
+    }
+}
+
+impl Cursor {
+    /// Take enough opportunities for `len` bytes, returning when the last of
+    /// them comes. `at` gives the time of an opportunity, `first` the first
+    /// one at or after `now`.
+    fn deliver(
+        &mut self,
+        at: impl Fn(u64) -> Instant,
+        first: impl Fn() -> u64,
+        now: Instant,
+        free_at: Instant,
+        len: usize,
+    ) -> Instant {
+        // Opportunities nobody used are gone.
+        if free_at < now {
+            self.credit = 0;
+        }
+        if at(self.next) < now {
+            self.next = first();
+        }
+        let mut departure = free_at;
+        let mut remaining = len;
+        while remaining > self.credit {
+            remaining -= self.credit;
+            self.credit = MTU;
+            departure = at(self.next);
+            self.next += 1;
+        }
+        self.credit -= remaining;
+        departure
+    }
+}
+
+/// Index of the first opportunity at or after `elapsed`.
+fn first_at(times: &[u64], period: u64, elapsed: Duration) -> u64 {
+    let elapsed = elapsed.as_nanos();
+    let period_ns = period as u128 * 1_000_000;
+    let round = (elapsed / period_ns) as u64;
+    let offset = elapsed % period_ns;
+    let i = times.partition_point(|&t| (t as u128 * 1_000_000) < offset);
+    round * times.len() as u64 + i as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn parses_mahimahi_and_csv() {
+        let trace = Trace::parse("# uplink\n1\n\n2\n2\n4\n").unwrap();
+        let Trace::Opportunities { times, period } = trace else {
+            panic!("not mahimahi: {:?}", trace);
+        };
+        assert_eq!((times, period), (vec![1, 2, 2, 4], 4));
+
+        let trace =
+            Trace::parse("time_ms,rate_mbit,rtt_ms\n0, 10, 20\n100,0,20\n150,1.5,40\n").unwrap();
+        let Trace::Schedule(steps) = trace else {
+            panic!("not csv: {:?}", trace);
+        };
+        let steps: Vec<_> = steps
+            .iter()
+            .map(|s| (s.at_ms, s.rate_mbit, s.rtt))
+            .collect();
+        assert_eq!(
+            steps,
+            [(0, 10.0, 20 * MS), (100, 0.0, 20 * MS), (150, 1.5, 40 * MS)]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_traces() {
+        for text in [
+            "",
+            "# only a comment\n",
+            "1\n2\nthree\n",
+            "1\n-2\n",
+            "5\n3\n7\n",
+            "0,10,20\n100,5\n",
+            "0,10,20\nlater,5,20\n",
+            "0,10,-1\n",
+            "0,10,20\n100,0,20\n",
+            "100,10,20\n0,10,20\n",
+        ] {
+            let err = Trace::parse(text).expect_err(text);
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn replays_opportunities_across_a_wrap() {
+        let epoch = Instant::now();
+        let trace = Trace::parse("1\n2\n4\n").unwrap();
+        let mut replay = Replay::new(trace, epoch);
+        let mut free_at = epoch;
+        let mut departures = Vec::new();
+        for _ in 0..5 {
+            free_at = replay.serialize(epoch, free_at, MTU);
+            departures.push(free_at - epoch);
+        }
+        assert_eq!(departures, [MS, 2 * MS, 4 * MS, 5 * MS, 6 * MS]);
+
+        // Small packets share an opportunity.
+        let shared = replay.serialize(epoch, free_at, MTU / 3);
+        assert_eq!(shared - epoch, 8 * MS);
+        assert_eq!(replay.serialize(epoch, shared, MTU / 3), shared);
+    }
+
+    #[test]
+    fn skips_opportunities_nobody_used() {
+        let epoch = Instant::now();
+        let trace = Trace::parse("1\n2\n4\n").unwrap();
+        let mut replay = Replay::new(trace, epoch);
+        let now = epoch + 9 * MS + MS / 2;
+        assert_eq!(replay.serialize(now, epoch, MTU) - epoch, 10 * MS);
+        assert_eq!(replay.serialize(now, now, MTU) - epoch, 12 * MS);
+    }
+
+    #[test]
+    fn follows_the_schedule_through_an_outage() {
+        let epoch = Instant::now();
+        let trace = Trace::parse("0,10,20\n100,0,20\n200,1,40\n").unwrap();
+        let mut replay = Replay::new(trace, epoch);
+        assert_eq!(replay.serialize(epoch, epoch, 1250) - epoch, MS);
+        assert_eq!(replay.delay(epoch + 50 * MS), 10 * MS);
+
+        let queued = epoch + 150 * MS;
+        assert_eq!(replay.serialize(queued, queued, 1250) - epoch, 210 * MS);
+        assert_eq!(replay.delay(epoch + 250 * MS), 20 * MS);
+    }
+}
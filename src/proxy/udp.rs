@@ -0,0 +1,212 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use log::debug;
+use mio::net::UdpSocket;
+use mio::{Events, Interest, Poll, Token};
+
+use super::link::Link;
+use crate::transport::unspecified_for;
+
+const LISTENER: Token = Token(0);
+
+/// Larger than any UDP payload.
+const MAX_DATAGRAM: usize = 1 << 16;
+
+/// A client of the relay, with a socket of its own towards the server so the
+/// server tells clients apart.
+struct Session {
+    client: SocketAddr,
+    upstream: UdpSocket,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// From a client to the server.
+    Up,
+    /// From the server to a client.
+    Down,
+}
+
+/// A datagram waiting to come out of its link.
+struct Pending {
+    at: Instant,
+    /// Tie breaker keeping datagrams due at the same time in order.
+    seq: u64,
+    direction: Direction,
+    session: usize,
+    payload: Vec<u8>,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    /// Reversed, the heap pops the earliest first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+
+/// Relays datagrams between clients and `server` through one link each way,
+/// on a single mio event loop.
+pub(crate) struct UdpRelay {
+    poll: Poll,
+    listener: UdpSocket,
+    server: SocketAddr,
+    sessions: Vec<Session>,
+    by_client: HashMap<SocketAddr, usize>,
+    up: Link,
+    down: Link,
+    pending: BinaryHeap<Pending>,
+    seq: u64,
+}
+
+impl UdpRelay {
+    pub(crate) fn bind(
+        listen: SocketAddr,
+        server: SocketAddr,
+        up: Link,
+        down: Link,
+    ) -> io::Result<Self> {
+        let poll = Poll::new()?;
+        let mut listener = UdpSocket::bind(listen)?;
+        poll.registry()
+            .register(&mut listener, LISTENER, Interest::READABLE)?;
+        Ok(Self {
+            poll,
+            listener,
+            server,
+            sessions: Vec::new(),
+            by_client: HashMap::new(),
+            up,
+            down,
+            pending: BinaryHeap::new(),
+            seq: 0,
+        })
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Relay forever.
+    pub(crate) fn run(mut self) -> io::Result<()> {
+        let mut events = Events::with_capacity(64);
+        let mut buf = vec![0; MAX_DATAGRAM];
+        loop {
+            let timeout = self
+                .pending
+                .peek()
+                .map(|p| p.at.saturating_duration_since(Instant::now()));
+            match self.poll.poll(&mut events, timeout) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => res?,
+            }
+            for event in events.iter() {
+                match event.token() {
+                    LISTENER => self.drain_clients(&mut buf)?,
+                    Token(i) => self.drain_server(i - 1, &mut buf)?,
+                }
+            }
+            self.deliver()?;
+        }
+    }
+
+    fn drain_clients(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        loop {
+            let (n, from) = match self.listener.recv_from(buf) {
+                Ok(res) => res,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let session = match self.by_client.get(&from) {
+                Some(&session) => session,
+                None => self.open(from)?,
+            };
+            self.enqueue(Direction::Up, session, &buf[..n]);
+        }
+    }
+
+    fn drain_server(&mut self, session: usize, buf: &mut [u8]) -> io::Result<()> {
+        loop {
+            let n = match self.sessions[session].upstream.recv(buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => continue,
+                Err(e) => return Err(e),
+            };
+            self.enqueue(Direction::Down, session, &buf[..n]);
+        }
+    }
+
+    fn open(&mut self, client: SocketAddr) -> io::Result<usize> {
+        let session = self.sessions.len();
+        let mut upstream = UdpSocket::bind(unspecified_for(&self.server))?;
+        upstream.connect(self.server)?;
+        self.poll
+            .registry()
+            .register(&mut upstream, Token(session + 1), Interest::READABLE)?;
+        self.sessions.push(Session { client, upstream });
+        self.by_client.insert(client, session);
+        debug!("relaying {} to {}", client, self.server);
+        Ok(session)
+    }
+
+    fn enqueue(&mut self, direction: Direction, session: usize, payload: &[u8]) {
+        let link = match direction {
+            Direction::Up => &mut self.up,
+            Direction::Down => &mut self.down,
+        };
+        let mut arrivals = Vec::with_capacity(2);
+        link.send(Instant::now(), payload.len(), &mut arrivals);
+        for at in arrivals {
+            self.seq += 1;
+            self.pending.push(Pending {
+                at,
+                seq: self.seq,
+                direction,
+                session,
+                payload: payload.to_vec(),
+            });
+        }
+    }
+
+    /// Send every datagram that is due. One the socket has no room for is
+    /// dropped, as a full interface queue would.
+    fn deliver(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        while self.pending.peek().is_some_and(|p| p.at <= now) {
+            let p = self.pending.pop().expect("peeked");
+            let session = &self.sessions[p.session];
+            let res = match p.direction {
+                Direction::Up => session.upstream.send(&p.payload),
+                Direction::Down => self.listener.send_to(&p.payload, session.client),
+            };
+            match res {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    debug!("relay dropped a datagram, socket full");
+                }
+                // The server not listening yet is reported as refused.
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+//! QUIC over quinn.
+//!
+//! quinn runs its endpoint on any [`quinn::Runtime`], which provides its UDP
+//! socket, timers and background tasks. tokio and async-std come with quinn,
+//! monoio has an adapter here, see [`MonoioRuntime`].
+
+mod monoio;
+
+pub use self::monoio::MonoioRuntime;
+
+use std::io;
+use std::marker::PhantomData;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+
+use log::{debug, error};
+use quinn::{
+    AsyncStdRuntime, ClientConfig, Connecting, Connection, Endpoint, EndpointConfig, RecvStream,
+    SendStream, ServerConfig, TokioRuntime, TransportConfig,
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::transport::unspecified_for;
+use crate::{tls, Crypto, Delivery, Listener, StreamKind, Transport, TransportOpt};
+
+pub type AsyncStdQuinn = QuinnStream<AsyncStdRuntime>;
+pub type MonoioQuinn = QuinnStream<MonoioRuntime>;
+pub type TokioQuinn = QuinnStream<TokioRuntime>;
+
+/// A [`quinn::Runtime`] endpoints can be created on.
+pub trait QuinnRuntime: quinn::Runtime + Sized {
+    fn new() -> Self;
+}
+
+impl QuinnRuntime for TokioRuntime {
+    fn new() -> Self {
+        TokioRuntime
+    }
+}
+
+impl QuinnRuntime for AsyncStdRuntime {
+    fn new() -> Self {
+        AsyncStdRuntime
+    }
+}
+
+/// A quinn stream, plaintext through quinn-plaintext or encrypted with
+/// rustls, see `--crypto`. Unidirectional streams pair with one the peer
+/// opens, see [`StreamKind::Uni`].
+pub struct QuinnStream<R> {
+    connection: Connection,
+    /// Opened on the first send if `None`.
+    send: Option<SendStream>,
+    /// The peer's next unidirectional stream, taken in on the first recv.
+    recv: Option<RecvStream>,
+    runtime: PhantomData<R>,
+}
+
+/// A stream a client opened, on the connection it belongs to.
+type Accepted = (Connection, Option<SendStream>, Option<RecvStream>);
+
+/// Connections are accepted and their streams taken in by tasks of the
+/// endpoint's runtime, so that new streams of one connection never wait
+/// behind the handshake of another.
+pub struct QuinnListener<R> {
+    endpoint: Endpoint,
+    streams: UnboundedReceiver<Accepted>,
+    runtime: PhantomData<R>,
+}
+
+impl<R> QuinnStream<R> {
+    fn new((connection, send, recv): Accepted) -> Self {
+        Self {
+            connection,
+            send,
+            recv,
+            runtime: PhantomData,
+        }
+    }
+}
+
+/// An endpoint on a socket bound to `addr`, serving with `server` if given.
+fn endpoint<R: QuinnRuntime>(
+    addr: SocketAddr,
+    server: Option<ServerConfig>,
+) -> io::Result<Endpoint> {
+    let socket = UdpSocket::bind(addr)?;
+    Endpoint::new(
+        EndpointConfig::default(),
+        server,
+        socket,
+        Arc::new(R::new()),
+    )
+}
+
+impl<R: QuinnRuntime> Transport for QuinnStream<R> {
+    type Listener = QuinnListener<R>;
+
+    const DELIVERY: Delivery = Delivery::Stream;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<QuinnListener<R>> {
+        let mut config = match opt.crypto.unwrap_or(Crypto::Plaintext) {
+            Crypto::Plaintext => quinn_plaintext::server_config(),
+            Crypto::Tls => ServerConfig::with_crypto(Arc::new(tls::server_config(opt)?)),
+        };
+        config.transport_config(transport_config(opt));
+        let endpoint = endpoint::<R>(addr, Some(config))?;
+        let (tx, streams) = mpsc::unbounded_channel();
+        R::new().spawn(Box::pin(accept_connections::<R>(endpoint.clone(), tx)));
+        Ok(QuinnListener {
+            endpoint,
+            streams,
+            runtime: PhantomData,
+        })
+    }
+
+    async fn connect(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self> {
+        let mut endpoint = endpoint::<R>(unspecified_for(&addr), None)?;
+        let mut config = match opt.crypto.unwrap_or(Crypto::Plaintext) {
+            Crypto::Plaintext => quinn_plaintext::client_config(),
+            Crypto::Tls => ClientConfig::new(Arc::new(tls::client_config(opt)?)),
+        };
+        config.transport_config(transport_config(opt));
+        endpoint.set_default_client_config(config);
+
+        let connection = endpoint
+            .connect(addr, "localhost")
+            .map_err(io::Error::other)?
+            .await
+            .map_err(io::Error::other)?;
+        debug!("connected to {}", connection.remote_address());
+        let (send, recv) = connection.open_bi().await.map_err(io::Error::other)?;
+        Ok(Self::new((connection, Some(send), Some(recv))))
+    }
+
+    async fn open_stream(&mut self, kind: StreamKind) -> io::Result<Self> {
+        let connection = self.connection.clone();
+        let (send, recv) = match kind {
+            StreamKind::Bi => {
+                let (send, recv) = connection.open_bi().await.map_err(io::Error::other)?;
+                (send, Some(recv))
+            }
+            StreamKind::Uni => (connection.open_uni().await.map_err(io::Error::other)?, None),
+        };
+        Ok(Self::new((connection, Some(send), recv)))
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        Ok(self.send_stream().await?.write_all(buf).await?)
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.recv_stream().await?.read(buf).await?.unwrap_or(0))
+    }
+
+    async fn finish(&mut self) -> io::Result<()> {
+        Ok(self.send_stream().await?.finish().await?)
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.finish().await?;
+        let recv = self.recv_stream().await?;
+        let mut buf = [0; 4096];
+        while recv.read(&mut buf).await?.is_some() {}
+        Ok(())
+    }
+}
+
+impl<R> QuinnStream<R> {
+    async fn send_stream(&mut self) -> io::Result<&mut SendStream> {
+        if self.send.is_none() {
+            let send = self.connection.open_uni().await.map_err(io::Error::other)?;
+            self.send = Some(send);
+        }
+        Ok(self.send.as_mut().expect("just opened"))
+    }
+
+    async fn recv_stream(&mut self) -> io::Result<&mut RecvStream> {
+        if self.recv.is_none() {
+            let recv = self
+                .connection
+                .accept_uni()
+                .await
+                .map_err(io::Error::other)?;
+            self.recv = Some(recv);
+        }
+        Ok(self.recv.as_mut().expect("just accepted"))
+    }
+}
+
+/// Settings of both sides. quinn segments its sends only with `--gso`, its
+/// socket coalesces receives whenever the kernel does.
+fn transport_config(opt: &TransportOpt) -> Arc<TransportConfig> {
+    let mut config = TransportConfig::default();
+    config.enable_segmentation_offload(opt.gso);
+    Arc::new(config)
+}
+
+impl<R: QuinnRuntime> Listener for QuinnListener<R> {
+    type Conn = QuinnStream<R>;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    async fn accept(&mut self) -> io::Result<QuinnStream<R>> {
+        let accepted = self.streams.recv().await;
+        let accepted = accepted
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "endpoint closed"))?;
+        Ok(QuinnStream::new(accepted))
+    }
+}
+
+/// Take in every connection `endpoint` accepts, on a task of its own.
+async fn accept_connections<R: QuinnRuntime>(
+    endpoint: Endpoint,
+    streams: UnboundedSender<Accepted>,
+) {
+    while let Some(connecting) = endpoint.accept().await {
+        R::new().spawn(Box::pin(accept_streams::<R>(connecting, streams.clone())));
+    }
+}
+
+/// Hand every stream the client opens over to the listener, until the
+/// connection closes. Unidirectional ones come in on a task of their own.
+async fn accept_streams<R: QuinnRuntime>(
+    connecting: Connecting,
+    streams: UnboundedSender<Accepted>,
+) {
+    let connection = match connecting.await {
+        Ok(connection) => connection,
+        Err(e) => {
+            error!("handshake failed: {}", e);
+            return;
+        }
+    };
+    debug!("new connection from {}", connection.remote_address());
+    R::new().spawn(Box::pin(accept_uni_streams(
+        connection.clone(),
+        streams.clone(),
+    )));
+    loop {
+        match connection.accept_bi().await {
+            Ok((send, recv)) => {
+                if streams
+                    .send((connection.clone(), Some(send), Some(recv)))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Err(e) => {
+                debug!(
+                    "connection from {} closed: {}",
+                    connection.remote_address(),
+                    e
+                );
+                return;
+            }
+        }
+    }
+}
+
+/// The unidirectional streams of [`accept_streams`], answered on streams the
+/// server opens.
+async fn accept_uni_streams(connection: Connection, streams: UnboundedSender<Accepted>) {
+    while let Ok(recv) = connection.accept_uni().await {
+        if streams
+            .send((connection.clone(), None, Some(recv)))
+            .is_err()
+        {
+            return;
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+
+use log::debug;
+use monoio::buf::IoBufMut;
+use monoio::net::udp::UdpSocket;
+use monoio::time::Sleep;
+use monoio::BufResult;
+use quinn::udp::{RecvMeta, Transmit, UdpState};
+use quinn::{AsyncTimer, AsyncUdpSocket, Runtime};
+
+use super::QuinnRuntime;
+
+/// Runs quinn endpoints on the monoio runtime of the current thread.
+///
+/// quinn asks for `Send` timers, sockets and tasks, monoio's are not. They
+/// are only sound here as long as they never leave the thread they were made
+/// on, which holds when:
+///
+/// - quinn itself starts no threads, it only polls and drops them from the
+///   tasks of its driver and from the handles of its endpoint, connections
+///   and streams;
+/// - `spawn` puts those tasks on the monoio runtime of the current thread,
+///   and monoio never moves a task to another thread;
+/// - every handle made with this runtime stays on the thread that made it.
+///   The `Send` quinn gives them does not allow otherwise, sending one to
+///   another thread and polling or dropping it there is undefined behavior.
+///   The benchmark makes and uses each of them on one runtime thread.
+#[derive(Debug)]
+pub struct MonoioRuntime;
+
+impl QuinnRuntime for MonoioRuntime {
+    fn new() -> Self {
+        MonoioRuntime
+    }
+}
+
+impl Runtime for MonoioRuntime {
+    fn new_timer(&self, i: Instant) -> Pin<Box<dyn AsyncTimer>> {
+        let sleep = monoio::time::sleep_until(monoio::time::Instant::from_std(i));
+        Box::pin(Timer(Box::pin(sleep)))
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        monoio::spawn(future);
+    }
+
+    fn wrap_udp_socket(&self, socket: std::net::UdpSocket) -> io::Result<Box<dyn AsyncUdpSocket>> {
+        // Needed by the legacy driver, harmless with io_uring.
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from_std(socket)?;
+        Ok(Box::new(Socket {
+            local_addr: socket.local_addr()?,
+            socket: Rc::new(socket),
+            recv: RefCell::new(None),
+            send: RefCell::new(None),
+            buf: RefCell::new(Some(Vec::new())),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct Timer(Pin<Box<Sleep>>);
+
+// SAFETY: only the task or handle owning the timer polls and drops it, and
+// `MonoioRuntime` keeps those on the thread whose monoio runtime made it.
+unsafe impl Send for Timer {}
+
+impl AsyncTimer for Timer {
+    fn reset(mut self: Pin<&mut Self>, i: Instant) {
+        self.0.as_mut().reset(monoio::time::Instant::from_std(i))
+    }
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        self.0.as_mut().poll(cx)
+    }
+}
+
+type RecvFuture = Pin<Box<dyn Future<Output = BufResult<(usize, SocketAddr), Vec<u8>>>>>;
+
+/// Sends transmits in order, the second field is how many.
+type SendFuture = (Pin<Box<dyn Future<Output = ()>>>, usize);
+
+/// A monoio socket behind quinn's poll interface.
+///
+/// io_uring needs owned buffers, so operations are started as futures of
+/// their own and kept until they complete. A send takes every transmit it is
+/// given: quinn offers the same ones again while it is pending.
+struct Socket {
+    socket: Rc<UdpSocket>,
+    local_addr: SocketAddr,
+    recv: RefCell<Option<RecvFuture>>,
+    send: RefCell<Option<SendFuture>>,
+    /// Receive buffer between receives, a cancelled one takes it along.
+    buf: RefCell<Option<Vec<u8>>>,
+}
+
+// SAFETY: the `Rc` and the pending operations are only touched by the
+// endpoint's driver and handles, which `MonoioRuntime` keeps on the thread
+// whose monoio runtime registered the socket. No clone of the `Rc` escapes
+// the socket but those owned by its own operations, which run there too.
+unsafe impl Send for Socket {}
+
+impl fmt::Debug for Socket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Socket")
+            .field("local_addr", &self.local_addr)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncUdpSocket for Socket {
+    fn poll_send(
+        &self,
+        _state: &UdpState,
+        cx: &mut Context,
+        transmits: &[Transmit],
+    ) -> Poll<io::Result<usize>> {
+        let mut send = self.send.borrow_mut();
+        let (future, n) = send.get_or_insert_with(|| {
+            let socket = self.socket.clone();
+            let count = transmits.len();
+            let transmits = transmits.to_vec();
+            let future = async move {
+                for transmit in transmits {
+                    let size = transmit.segment_size.unwrap_or(transmit.contents.len());
+                    for start in (0..transmit.contents.len()).step_by(size.max(1)) {
+                        let end = transmit.contents.len().min(start + size);
+                        let datagram = transmit.contents.slice(start..end);
+                        let (res, _) = socket.send_to(datagram, transmit.destination).await;
+                        // Lost like any datagram, as quinn's own sockets do.
+                        if let Err(e) = res {
+                            debug!("send to {}: {}", transmit.destination, e);
+                        }
+                    }
+                }
+            };
+            (Box::pin(future), count)
+        });
+        ready!(future.as_mut().poll(cx));
+        let n = *n;
+        *send = None;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [io::IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        let mut recv = self.recv.borrow_mut();
+        let future = recv.get_or_insert_with(|| {
+            let socket = self.socket.clone();
+            let mut owned = self.buf.borrow_mut().take().unwrap_or_default();
+            owned.clear();
+            let len = bufs[0].len();
+            owned.reserve(len);
+            Box::pin(async move {
+                let (res, owned) = socket.recv_from(owned.slice_mut(..len)).await;
+                (res, owned.into_inner())
+            })
+        });
+        let (res, owned) = ready!(future.as_mut().poll(cx));
+        *recv = None;
+        let res = res.map(|(len, addr)| {
+            bufs[0][..len].copy_from_slice(&owned[..len]);
+            meta[0] = RecvMeta {
+                addr,
+                len,
+                stride: len,
+                ecn: None,
+                dst_ip: None,
+            };
+            1
+        });
+        self.buf.replace(Some(owned));
+        Poll::Ready(res)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
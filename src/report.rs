@@ -0,0 +1,548 @@
+//! Where and how both sides write their measurements.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::{Args, ValueEnum};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line.
+    Json,
+    /// Comma separated values with a header row.
+    Csv,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReportOpt {
+    /// How results are written.
+    #[clap(long, value_enum, default_value_t = Format::Text)]
+    pub output: Format,
+
+    /// Write results to this file instead of stdout.
+    #[clap(long, value_name = "FILE")]
+    pub output_file: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Client,
+    Server,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::Client => "client",
+            Role::Server => "server",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleKind {
+    /// One reporting interval.
+    Interval,
+    /// The whole run.
+    Total,
+}
+
+impl SampleKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            SampleKind::Interval => "interval",
+            SampleKind::Total => "total",
+        }
+    }
+}
+
+/// Measurements over one reporting interval, or over the whole run.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Sample {
+    /// Milliseconds since the unix epoch at the end of the interval.
+    pub timestamp_ms: u64,
+    pub kind: SampleKind,
+    pub role: Role,
+    pub proto: String,
+    pub runtime: String,
+    /// Bytes that completed a round trip, or were echoed by a server.
+    pub bytes: u64,
+    /// Round trips completed, or messages echoed by a server.
+    pub packets: u64,
+    pub interval_ms: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub p999_us: u64,
+    pub max_us: u64,
+    /// CPU time of the whole process, user and system.
+    pub cpu_us: u64,
+    /// Bytes per second of CPU time, how cheaply they were moved.
+    pub bytes_per_cpu_s: u64,
+    /// Lost and misdelivered messages, for datagrams and verified payloads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<Integrity>,
+    /// Throughput across intervals, only for the whole run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spread: Option<Spread>,
+    /// How the client's connections shared the throughput, only for the
+    /// whole run of several.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connections: Option<Fairness>,
+    /// How the streams of all connections shared it, only for the whole run
+    /// with several streams per connection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streams: Option<Fairness>,
+}
+
+impl Sample {
+    /// Throughput in MB/s.
+    pub fn rate(&self) -> u64 {
+        self.bytes / (1000 * self.interval_ms.max(1))
+    }
+
+    /// CPU time over wall time, above 100 when several cores are busy.
+    pub fn cpu_percent(&self) -> u64 {
+        self.cpu_us / (10 * self.interval_ms.max(1))
+    }
+}
+
+/// Messages that did not come back the way they were sent.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Integrity {
+    pub corrupt: u64,
+    pub reordered: u64,
+    pub duplicated: u64,
+    /// Not echoed within the loss timeout, and replaced in the window.
+    #[serde(default)]
+    pub lost: u64,
+    /// Echoed after having been counted as lost.
+    #[serde(default)]
+    pub late: u64,
+}
+
+impl Integrity {
+    pub fn add(&mut self, other: &Integrity) {
+        self.corrupt += other.corrupt;
+        self.reordered += other.reordered;
+        self.duplicated += other.duplicated;
+        self.lost += other.lost;
+        self.late += other.late;
+    }
+}
+
+/// Distribution of per-interval throughput, in MB/s.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Spread {
+    pub intervals: u64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Spread {
+    /// `None` without any full interval.
+    pub fn of(rates: &[f64]) -> Option<Self> {
+        if rates.is_empty() {
+            return None;
+        }
+        let n = rates.len() as f64;
+        let mean = rates.iter().sum::<f64>() / n;
+        let variance = rates.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n;
+        Some(Self {
+            intervals: rates.len() as u64,
+            mean,
+            stddev: variance.sqrt(),
+            min: rates.iter().copied().fold(f64::INFINITY, f64::min),
+            max: rates.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+}
+
+/// Throughput of every connection, or stream, over the whole run, in MB/s.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Fairness {
+    /// Number of connections, or streams.
+    pub connections: u64,
+    pub min: f64,
+    pub max: f64,
+    /// Jain's index, 1 when all connections got the same throughput and
+    /// 1/n when one got all of it.
+    pub index: f64,
+    pub rates: Vec<f64>,
+    /// Round trip p99 of each. Streams of one connection that stall behind
+    /// each other's losses share a high one.
+    #[serde(default)]
+    pub p99_us: Vec<u64>,
+}
+
+impl Fairness {
+    /// `None` for fewer than two connections.
+    pub fn of(rates: &[f64], p99_us: &[u64]) -> Option<Self> {
+        if rates.len() < 2 {
+            return None;
+        }
+        let n = rates.len() as f64;
+        let sum = rates.iter().sum::<f64>();
+        let squares = rates.iter().map(|r| r * r).sum::<f64>();
+        let index = if squares > 0.0 {
+            sum * sum / (n * squares)
+        } else {
+            1.0
+        };
+        Some(Self {
+            connections: rates.len() as u64,
+            min: rates.iter().copied().fold(f64::INFINITY, f64::min),
+            max: rates.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            index,
+            rates: rates.to_vec(),
+            p99_us: p99_us.to_vec(),
+        })
+    }
+
+    /// Lowest and highest p99.
+    pub fn p99_range(&self) -> (u64, u64) {
+        let min = self.p99_us.iter().copied().min().unwrap_or(0);
+        let max = self.p99_us.iter().copied().max().unwrap_or(0);
+        (min, max)
+    }
+
+    fn write_text(&self, out: &mut dyn Write, unit: &str) -> io::Result<()> {
+        let rates: Vec<_> = self.rates.iter().map(|r| format!("{:.1}", r)).collect();
+        let (p99_min, p99_max) = self.p99_range();
+        writeln!(
+            out,
+            "{} {}: min={:.1} max={:.1} MB/s fairness={:.3} ({}) p99={}..{}us",
+            self.connections,
+            unit,
+            self.min,
+            self.max,
+            self.index,
+            rates.join(" "),
+            p99_min,
+            p99_max,
+        )
+    }
+}
+
+const CSV_HEADER: &str =
+    "timestamp_ms,kind,role,proto,runtime,bytes,packets,interval_ms,p50_us,p90_us,p99_us,p999_us,max_us,cpu_us,bytes_per_cpu_s,corrupt,reordered,duplicated,lost,late,intervals,mean_mbps,stddev_mbps,min_mbps,max_mbps,connections,conn_min_mbps,conn_max_mbps,fairness,streams,stream_min_mbps,stream_max_mbps,stream_fairness,stream_p99_min_us,stream_p99_max_us";
+
+/// Writes samples in the selected format.
+pub struct Reporter {
+    format: Format,
+    out: Box<dyn Write + Send>,
+    role: Role,
+    proto: String,
+    runtime: String,
+    header_written: bool,
+}
+
+impl Reporter {
+    pub fn new(
+        opt: &ReportOpt,
+        role: Role,
+        proto: impl ToString,
+        runtime: impl ToString,
+    ) -> io::Result<Self> {
+        let out: Box<dyn Write + Send> = match &opt.output_file {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(io::stdout()),
+        };
+        Ok(Self {
+            format: opt.output,
+            out,
+            role,
+            proto: proto.to_string(),
+            runtime: runtime.to_string(),
+            header_written: false,
+        })
+    }
+
+    /// Fill in the fields the reporter knows about.
+    pub fn sample(&self, kind: SampleKind) -> Sample {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        Sample {
+            timestamp_ms,
+            kind,
+            role: self.role,
+            proto: self.proto.clone(),
+            runtime: self.runtime.clone(),
+            bytes: 0,
+            packets: 0,
+            interval_ms: 0,
+            p50_us: 0,
+            p90_us: 0,
+            p99_us: 0,
+            p999_us: 0,
+            max_us: 0,
+            cpu_us: 0,
+            bytes_per_cpu_s: 0,
+            integrity: None,
+            spread: None,
+            connections: None,
+            streams: None,
+        }
+    }
+
+    /// Write one sample, failures are logged so they never stop a benchmark.
+    pub fn write(&mut self, sample: &Sample) {
+        if let Err(e) = self.try_write(sample) {
+            error!("write sample failed: {}", e);
+        }
+    }
+
+    fn try_write(&mut self, s: &Sample) -> io::Result<()> {
+        match self.format {
+            Format::Text => {
+                if s.kind == SampleKind::Total {
+                    write!(self.out, "total: ")?;
+                }
+                write!(self.out, "{} MB/s", s.rate())?;
+                if s.role == Role::Client {
+                    write!(
+                        self.out,
+                        " rtt p50={}us p90={}us p99={}us p99.9={}us max={}us",
+                        s.p50_us, s.p90_us, s.p99_us, s.p999_us, s.max_us,
+                    )?;
+                }
+                write!(
+                    self.out,
+                    " cpu={}% {} MB/cpu-s",
+                    s.cpu_percent(),
+                    s.bytes_per_cpu_s / 1_000_000
+                )?;
+                if let Some(i) = &s.integrity {
+                    write!(
+                        self.out,
+                        " lost={} late={} reordered={} duplicated={} corrupt={}",
+                        i.lost, i.late, i.reordered, i.duplicated, i.corrupt
+                    )?;
+                }
+                writeln!(self.out)?;
+                if let Some(r) = &s.spread {
+                    writeln!(
+                        self.out,
+                        "{} intervals: mean={:.1} stddev={:.1} min={:.1} max={:.1} MB/s",
+                        r.intervals, r.mean, r.stddev, r.min, r.max,
+                    )?;
+                }
+                if let Some(f) = &s.connections {
+                    f.write_text(&mut self.out, "connections")?;
+                }
+                if let Some(f) = &s.streams {
+                    f.write_text(&mut self.out, "streams")?;
+                }
+            }
+            Format::Json => {
+                serde_json::to_writer(&mut self.out, s)?;
+                writeln!(self.out)?;
+            }
+            Format::Csv => {
+                if !self.header_written {
+                    writeln!(self.out, "{}", CSV_HEADER)?;
+                    self.header_written = true;
+                }
+                write!(
+                    self.out,
+                    "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                    s.timestamp_ms,
+                    s.kind.as_str(),
+                    s.role.as_str(),
+                    s.proto,
+                    s.runtime,
+                    s.bytes,
+                    s.packets,
+                    s.interval_ms,
+                    s.p50_us,
+                    s.p90_us,
+                    s.p99_us,
+                    s.p999_us,
+                    s.max_us,
+                    s.cpu_us,
+                    s.bytes_per_cpu_s,
+                )?;
+                match &s.integrity {
+                    Some(i) => write!(
+                        self.out,
+                        ",{},{},{},{},{}",
+                        i.corrupt, i.reordered, i.duplicated, i.lost, i.late
+                    )?,
+                    None => write!(self.out, ",,,,,")?,
+                }
+                match &s.spread {
+                    Some(r) => write!(
+                        self.out,
+                        ",{},{:.3},{:.3},{:.3},{:.3}",
+                        r.intervals, r.mean, r.stddev, r.min, r.max,
+                    )?,
+                    None => write!(self.out, ",,,,,")?,
+                }
+                match &s.connections {
+                    Some(f) => write!(
+                        self.out,
+                        ",{},{:.3},{:.3},{:.3}",
+                        f.connections, f.min, f.max, f.index,
+                    )?,
+                    None => write!(self.out, ",,,,")?,
+                }
+                match &s.streams {
+                    Some(f) => {
+                        let (p99_min, p99_max) = f.p99_range();
+                        writeln!(
+                            self.out,
+                            ",{},{:.3},{:.3},{:.3},{},{}",
+                            f.connections, f.min, f.max, f.index, p99_min, p99_max,
+                        )?
+                    }
+                    None => writeln!(self.out, ",,,,,,")?,
+                }
+            }
+        }
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// A writer the test keeps a handle on.
+    #[derive(Clone, Default)]
+    pub(crate) struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Captured {
+        pub(crate) fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+
+        /// What a JSON reporter wrote.
+        pub(crate) fn samples(&self) -> Vec<Sample> {
+            let text = self.text();
+            text.lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn reporter(format: Format) -> (Reporter, Captured) {
+        let out = Captured::default();
+        let reporter = Reporter {
+            format,
+            out: Box::new(out.clone()),
+            role: Role::Client,
+            proto: "quinn".into(),
+            runtime: "tokio".into(),
+            header_written: false,
+        };
+        (reporter, out)
+    }
+
+    /// A total with every optional part filled in, and an interval without.
+    fn samples(reporter: &Reporter) -> [Sample; 2] {
+        let total = Sample {
+            bytes: 3_000_000,
+            packets: 3000,
+            interval_ms: 3000,
+            p50_us: 50,
+            p90_us: 90,
+            p99_us: 99,
+            p999_us: 999,
+            max_us: 1234,
+            cpu_us: 1_500_000,
+            bytes_per_cpu_s: 2_000_000,
+            integrity: Some(Integrity {
+                lost: 4,
+                late: 1,
+                ..Integrity::default()
+            }),
+            spread: Spread::of(&[0.5, 1.5]),
+            connections: Fairness::of(&[1.0, 2.0], &[90, 110]),
+            streams: Fairness::of(&[0.5, 0.5, 1.0, 1.0], &[80, 90, 100, 110]),
+            ..reporter.sample(SampleKind::Total)
+        };
+        [reporter.sample(SampleKind::Interval), total]
+    }
+
+    #[test]
+    fn json_parses_back() {
+        let (mut reporter, out) = reporter(Format::Json);
+        let written = samples(&reporter);
+        for sample in &written {
+            reporter.write(sample);
+        }
+        let read = out.samples();
+        assert_eq!(read.len(), written.len());
+        for (read, written) in read.iter().zip(&written) {
+            assert_eq!(read.kind, written.kind);
+            assert_eq!(read.bytes, written.bytes);
+            assert_eq!(read.p999_us, written.p999_us);
+            assert_eq!(read.integrity, written.integrity);
+            assert_eq!(read.spread.map(|s| s.mean), written.spread.map(|s| s.mean));
+            let p99 = |f: &Option<Fairness>| f.as_ref().map(Fairness::p99_range);
+            assert_eq!(p99(&read.connections), p99(&written.connections));
+            assert_eq!(p99(&read.streams), p99(&written.streams));
+        }
+    }
+
+    #[test]
+    fn csv_rows_match_the_header() {
+        let (mut reporter, out) = reporter(Format::Csv);
+        for sample in &samples(&reporter) {
+            reporter.write(sample);
+        }
+        let text = out.text();
+        let mut lines = text.lines();
+        let header: Vec<_> = lines.next().unwrap().split(',').collect();
+        assert_eq!(header.len(), 35);
+        let rows: Vec<Vec<_>> = lines.map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows.len(), 2);
+        for row in &rows {
+            assert_eq!(row.len(), header.len(), "{:?}", row);
+        }
+        let total = |name: &str| rows[1][header.iter().position(|h| *h == name).unwrap()];
+        assert_eq!(total("kind"), "total");
+        assert_eq!(total("bytes"), "3000000");
+        assert_eq!(total("p999_us"), "999");
+        assert_eq!(total("lost"), "4");
+        assert_eq!(total("mean_mbps"), "1.000");
+        assert_eq!(total("fairness"), "0.900");
+        assert_eq!(total("streams"), "4");
+        assert_eq!(total("stream_p99_max_us"), "110");
+        assert_eq!(
+            rows[0][header.iter().position(|h| *h == "lost").unwrap()],
+            ""
+        );
+    }
+}
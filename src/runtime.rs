@@ -0,0 +1,117 @@
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+use clap::ValueEnum;
+use tokio::task::LocalSet;
+
+/// Executor a benchmark runs on.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    /// Blocking sockets from the standard library.
+    Native,
+    /// Hand written mio event loops.
+    Mio,
+    /// Multi-threaded tokio scheduler.
+    Tokio,
+    /// Thread-per-core with io_uring.
+    Monoio,
+    /// The async-std global executor.
+    AsyncStd,
+}
+
+impl Runtime {
+    /// Run `fut` to completion on a fresh instance of this runtime.
+    pub fn block_on<F: Future>(self, fut: F) -> io::Result<F::Output> {
+        match self {
+            Runtime::Native | Runtime::Mio => Ok(block_on(fut)),
+            Runtime::Tokio => {
+                let rt = tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .build()?;
+                // Transports are not `Send`, their tasks stay on this thread.
+                Ok(LocalSet::new().block_on(&rt, fut))
+            }
+            Runtime::Monoio => {
+                let mut rt = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
+                    .enable_timer()
+                    .build()?;
+                Ok(rt.block_on(fut))
+            }
+            Runtime::AsyncStd => Ok(async_std::task::block_on(fut)),
+        }
+    }
+
+    /// Start `fut` next to the current task, from within [`Runtime::block_on`].
+    ///
+    /// Blocking runtimes have nothing to run it next to and finish `fut`
+    /// before returning.
+    pub fn spawn<F: Future<Output = ()> + 'static>(self, fut: F) {
+        match self {
+            Runtime::Native | Runtime::Mio => block_on(fut),
+            Runtime::Tokio => {
+                tokio::task::spawn_local(fut);
+            }
+            Runtime::Monoio => {
+                monoio::spawn(fut);
+            }
+            Runtime::AsyncStd => {
+                async_std::task::spawn_local(fut);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+/// Drive a future whose I/O blocks the calling thread, as the native and mio
+/// transports do, so it never has to be woken up.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::yield_now();
+    }
+}
+
+/// Run `futs` concurrently on the current task, returning their outputs in
+/// order once all are done. Only for runtimes whose I/O does not block.
+pub async fn join_all<F: Future>(futs: Vec<F>) -> Vec<F::Output> {
+    let mut futs: Vec<_> = futs.into_iter().map(|fut| Some(Box::pin(fut))).collect();
+    let mut outputs: Vec<_> = futs.iter().map(|_| None).collect();
+    poll_fn(|cx| {
+        let mut pending = false;
+        for (slot, output) in futs.iter_mut().zip(&mut outputs) {
+            let Some(fut) = slot else {
+                continue;
+            };
+            match fut.as_mut().poll(cx) {
+                Poll::Ready(value) => {
+                    *output = Some(value);
+                    *slot = None;
+                }
+                Poll::Pending => pending = true,
+            }
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    })
+    .await;
+    outputs
+        .into_iter()
+        .map(|output| output.expect("every future is done"))
+        .collect()
+}
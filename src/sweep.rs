@@ -0,0 +1,518 @@
+//! `bench sweep`: run a grid of server/client pairs, one pair of child
+//! processes per cell, and gather their results into a single table.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{Args, ValueEnum};
+
+use crate::bench::Proto;
+use crate::driver::WorkloadOpt;
+use crate::local::ServerProcess;
+use crate::report::{Sample, SampleKind};
+use crate::runtime::Runtime;
+use crate::{Crypto, StreamKind};
+
+/// Time a client gets beyond warmup and duration before it is killed.
+const GRACE: Duration = Duration::from_secs(10);
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    Markdown,
+    Csv,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SweepOpt {
+    /// Protocols to run, comma separated.
+    #[clap(long, value_enum, value_delimiter = ',', required = true)]
+    pub proto: Vec<Proto>,
+
+    /// Runtimes to run each protocol on, comma separated.
+    #[clap(long, value_enum, value_delimiter = ',', default_value = "tokio")]
+    pub runtime: Vec<Runtime>,
+
+    /// Payload sizes, comma separated [default: the transport's].
+    #[clap(long, value_delimiter = ',', value_name = "BYTES")]
+    pub payload_size: Vec<u64>,
+
+    /// In-flight windows, comma separated [default: the transport's].
+    #[clap(long, value_delimiter = ',', value_name = "N")]
+    pub inflight: Vec<u64>,
+
+    /// Segmentation offload off, on or both, e.g. `false,true`.
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = "false",
+        value_name = "BOOL"
+    )]
+    pub gso: Vec<bool>,
+
+    /// Server threads and client flows, comma separated.
+    #[clap(long, value_delimiter = ',', default_value = "1", value_name = "N")]
+    pub threads: Vec<u16>,
+
+    /// Client connections, comma separated [default: --threads].
+    #[clap(long, value_delimiter = ',', value_name = "N")]
+    pub connections: Vec<u64>,
+
+    /// Streams per connection, comma separated, QUIC only [default: 1].
+    #[clap(long, value_delimiter = ',', value_name = "N")]
+    pub streams: Vec<u64>,
+
+    /// Streams opened for every message, comma separated, QUIC only
+    /// [default: all messages on one stream].
+    #[clap(long, value_enum, value_delimiter = ',', value_name = "KIND")]
+    pub stream_per_message: Vec<StreamKind>,
+
+    /// Packet protection of QUIC, comma separated, e.g. `plaintext,tls`.
+    /// tquic only runs TLS [default: each stack's].
+    #[clap(long, value_enum, value_delimiter = ',')]
+    pub crypto: Vec<Crypto>,
+
+    /// Seconds each cell is measured.
+    #[clap(long, default_value = "10", value_name = "SECS")]
+    pub duration: u64,
+
+    /// Seconds each cell runs before measuring.
+    #[clap(long, default_value = "2", value_name = "SECS")]
+    pub warmup: u64,
+
+    /// Write the table to this file instead of stdout.
+    #[clap(long, value_name = "FILE")]
+    pub table: Option<String>,
+
+    #[clap(long, value_enum, default_value_t = TableFormat::Markdown)]
+    pub format: TableFormat,
+
+    /// Options passed to every server and client, e.g. `-- --cert my.crt`.
+    #[clap(last = true)]
+    pub extra: Vec<String>,
+}
+
+/// One combination of the grid.
+#[derive(Debug, Clone)]
+struct Cell {
+    proto: Proto,
+    runtime: Runtime,
+    crypto: Option<Crypto>,
+    workload: WorkloadOpt,
+    gso: bool,
+    threads: u16,
+}
+
+impl Cell {
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--proto".into(),
+            self.proto.to_string(),
+            "--runtime".into(),
+            self.runtime.to_string(),
+        ];
+        if let Some(crypto) = self.crypto {
+            args.extend(["--crypto".into(), crypto.to_string()]);
+        }
+        if self.gso {
+            args.push("--gso".into());
+        }
+        args.extend(["--threads".into(), self.threads.to_string()]);
+        args
+    }
+}
+
+/// What a cell measured, the client's summary and the server's totals.
+struct Measured {
+    client: Sample,
+    server_bytes: u64,
+    server_ms: u64,
+    server_cpu_us: u64,
+}
+
+/// Run every cell of the grid in turn and write the table.
+pub fn run(opt: &SweepOpt) -> io::Result<()> {
+    let cells = grid(opt);
+    let total = cells.len();
+    let mut rows = Vec::with_capacity(total);
+    for (i, cell) in cells.into_iter().enumerate() {
+        eprintln!(
+            "[{}/{}] {} on {}{}, payload {} inflight {} gso {} threads {} connections {} streams {}{}",
+            i + 1,
+            total,
+            cell.proto,
+            cell.runtime,
+            cell.crypto
+                .map_or(String::new(), |crypto| format!(" with {}", crypto)),
+            payload_size(&cell),
+            inflight(&cell),
+            cell.gso,
+            cell.threads,
+            connections(&cell),
+            cell.workload.streams(),
+            cell.workload
+                .stream_per_message
+                .map_or(String::new(), |kind| format!(", a {} stream per message", kind)),
+        );
+        let res = run_cell(opt, &cell, i);
+        if let Err(e) = &res {
+            eprintln!("  failed: {}", e);
+        }
+        rows.push((cell, res));
+    }
+
+    let mut out: Box<dyn Write> = match &opt.table {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    match opt.format {
+        TableFormat::Markdown => write_markdown(&mut out, &rows),
+        TableFormat::Csv => write_csv(&mut out, &rows),
+    }
+}
+
+/// An empty axis stands for the transport's default.
+fn axis<T: Copy>(values: &[T]) -> Vec<Option<T>> {
+    if values.is_empty() {
+        vec![None]
+    } else {
+        values.iter().copied().map(Some).collect()
+    }
+}
+
+fn grid(opt: &SweepOpt) -> Vec<Cell> {
+    let mut cells = Vec::new();
+    for &proto in &opt.proto {
+        for &crypto in &crypto_axis(opt, proto) {
+            for &runtime in &opt.runtime {
+                for &payload_size in &axis(&opt.payload_size) {
+                    for &inflight in &axis(&opt.inflight) {
+                        for &gso in &opt.gso {
+                            for &threads in &opt.threads {
+                                for &connections in &axis(&opt.connections) {
+                                    for &streams in &axis(&opt.streams) {
+                                        for &stream_per_message in &axis(&opt.stream_per_message) {
+                                            cells.push(Cell {
+                                                proto,
+                                                runtime,
+                                                crypto,
+                                                workload: WorkloadOpt {
+                                                    payload_size,
+                                                    inflight,
+                                                    connections,
+                                                    streams,
+                                                    stream_per_message,
+                                                    ..WorkloadOpt::default()
+                                                },
+                                                gso,
+                                                threads,
+                                            });
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    cells
+}
+
+/// `--crypto` only applies to QUIC, and tquic has no mode but TLS.
+fn crypto_axis(opt: &SweepOpt, proto: Proto) -> Vec<Option<Crypto>> {
+    match proto {
+        Proto::Quinn => axis(&opt.crypto),
+        _ => vec![None],
+    }
+}
+
+/// What protects the cell's traffic, if anything.
+fn crypto(cell: &Cell) -> String {
+    match (cell.proto, cell.crypto) {
+        (_, Some(crypto)) => crypto.to_string(),
+        (Proto::Quinn, None) => Crypto::Plaintext.to_string(),
+        (Proto::Tls | Proto::Tquic, None) => Crypto::Tls.to_string(),
+        (Proto::Tcp | Proto::Udp, None) => String::new(),
+    }
+}
+
+fn payload_size(cell: &Cell) -> usize {
+    cell.workload.payload_size(cell.proto.delivery())
+}
+
+fn inflight(cell: &Cell) -> usize {
+    cell.workload.inflight(cell.proto.delivery())
+}
+
+fn connections(cell: &Cell) -> u64 {
+    cell.workload.connections.unwrap_or(cell.threads.into())
+}
+
+fn run_cell(opt: &SweepOpt, cell: &Cell, index: usize) -> io::Result<Measured> {
+    let dir = env::temp_dir();
+    let prefix = format!("bench-sweep-{}-{}", std::process::id(), index);
+    let server_out = dir.join(format!("{}-server.json", prefix));
+    let client_out = dir.join(format!("{}-client.json", prefix));
+
+    let res = run_pair(opt, cell, &server_out, &client_out)
+        .and_then(|()| measured(&client_out, &server_out));
+    let _ = fs::remove_file(&server_out);
+    let _ = fs::remove_file(&client_out);
+    res
+}
+
+/// Run the cell's server and client, each writing samples to its file.
+fn run_pair(opt: &SweepOpt, cell: &Cell, server_out: &Path, client_out: &Path) -> io::Result<()> {
+    let exe = env::current_exe()?;
+    let server = ServerProcess::spawn(
+        Command::new(&exe)
+            .arg("server")
+            .args(cell.args())
+            .args([
+                "--listen",
+                "127.0.0.1:0",
+                "--output",
+                "json",
+                "--output-file",
+            ])
+            .arg(server_out)
+            .args(&opt.extra),
+        false,
+    )?;
+    let mut client = Command::new(&exe)
+        .arg("client")
+        .args(cell.args())
+        .arg("--connect-to")
+        .arg(server.addr().to_string())
+        .args(["--duration", &opt.duration.to_string()])
+        .args(["--warmup", &opt.warmup.to_string()])
+        .args(workload_args(&cell.workload))
+        .args(["--output", "json", "--output-file"])
+        .arg(client_out)
+        .args(&opt.extra)
+        .stdout(Stdio::null())
+        .spawn()?;
+    let limit = Duration::from_secs(opt.duration + opt.warmup) + GRACE;
+    wait_client(&mut client, limit)
+}
+
+fn workload_args(workload: &WorkloadOpt) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(size) = workload.payload_size {
+        args.extend(["--payload-size".to_string(), size.to_string()]);
+    }
+    if let Some(n) = workload.inflight {
+        args.extend(["--inflight".to_string(), n.to_string()]);
+    }
+    if let Some(n) = workload.connections {
+        args.extend(["--connections".to_string(), n.to_string()]);
+    }
+    if let Some(n) = workload.streams {
+        args.extend(["--streams".to_string(), n.to_string()]);
+    }
+    if let Some(kind) = workload.stream_per_message {
+        args.extend(["--stream-per-message".to_string(), kind.to_string()]);
+    }
+    args
+}
+
+fn wait_client(client: &mut Child, limit: Duration) -> io::Result<()> {
+    let started_at = Instant::now();
+    loop {
+        if let Some(status) = client.try_wait()? {
+            return match status.success() {
+                true => Ok(()),
+                false => Err(io::Error::other(format!("client exited with {}", status))),
+            };
+        }
+        if started_at.elapsed() > limit {
+            let _ = client.kill();
+            let _ = client.wait();
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "client timed out"));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn measured(client_out: &Path, server_out: &Path) -> io::Result<Measured> {
+    let client = samples(client_out)?
+        .into_iter()
+        .find(|s| s.kind == SampleKind::Total)
+        .ok_or_else(|| io::Error::other("client reported no summary"))?;
+
+    let mut measured = Measured {
+        client,
+        server_bytes: 0,
+        server_ms: 0,
+        server_cpu_us: 0,
+    };
+    for s in samples(server_out)? {
+        measured.server_bytes += s.bytes;
+        measured.server_ms += s.interval_ms;
+        measured.server_cpu_us += s.cpu_us;
+    }
+    Ok(measured)
+}
+
+fn samples(path: &Path) -> io::Result<Vec<Sample>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    BufReader::new(file)
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// CPU time of client and server together per byte they moved.
+fn cpu_per_byte(m: &Measured) -> Option<f64> {
+    let c = &m.client;
+    if c.bytes == 0 || m.server_bytes == 0 {
+        return None;
+    }
+    Some(c.cpu_us as f64 / c.bytes as f64 + m.server_cpu_us as f64 / m.server_bytes as f64)
+}
+
+/// The quinn plaintext cell that `cell` differs from in protocol and crypto
+/// only, the baseline its CPU per byte is compared with.
+fn baseline<'a>(rows: &'a [(Cell, io::Result<Measured>)], cell: &Cell) -> Option<&'a Measured> {
+    if !matches!(cell.proto, Proto::Quinn | Proto::Tquic) {
+        return None;
+    }
+    rows.iter().find_map(|(other, res)| {
+        let plain = other.proto == Proto::Quinn && crypto(other) == Crypto::Plaintext.to_string();
+        match res {
+            Ok(m) if plain && axes(other) == axes(cell) => Some(m),
+            _ => None,
+        }
+    })
+}
+
+/// Values of a finished cell, in table order.
+fn columns(m: &Measured, baseline: Option<&Measured>) -> [String; 14] {
+    let c = &m.client;
+    let (mean, stddev) = match &c.spread {
+        Some(r) => (r.mean, r.stddev),
+        None => (c.rate() as f64, 0.0),
+    };
+    let server_cpu = m.server_cpu_us / (10 * m.server_ms.max(1));
+    let server_per_cpu = match m.server_cpu_us {
+        0 => 0,
+        us => m.server_bytes / us,
+    };
+    [
+        format!("{:.1}", mean),
+        format!("{:.1}", stddev),
+        c.p50_us.to_string(),
+        c.p99_us.to_string(),
+        c.p999_us.to_string(),
+        c.cpu_percent().to_string(),
+        (c.bytes_per_cpu_s / 1_000_000).to_string(),
+        server_cpu.to_string(),
+        server_per_cpu.to_string(),
+        c.packets.to_string(),
+        c.connections
+            .as_ref()
+            .map_or(String::new(), |f| format!("{:.3}", f.index)),
+        c.streams
+            .as_ref()
+            .map_or(String::new(), |f| format!("{:.3}", f.index)),
+        c.streams
+            .as_ref()
+            .map_or(String::new(), |f| f.p99_range().1.to_string()),
+        match (cpu_per_byte(m), baseline.and_then(cpu_per_byte)) {
+            (Some(cpu), Some(plain)) => format!("{:.2}", cpu / plain),
+            _ => String::new(),
+        },
+    ]
+}
+
+const HEADER: [&str; 24] = [
+    "proto",
+    "crypto",
+    "runtime",
+    "payload",
+    "inflight",
+    "gso",
+    "threads",
+    "connections",
+    "streams",
+    "stream per message",
+    "MB/s",
+    "stddev",
+    "p50 us",
+    "p99 us",
+    "p99.9 us",
+    "client cpu %",
+    "client MB/cpu-s",
+    "server cpu %",
+    "server MB/cpu-s",
+    "round trips",
+    "fairness",
+    "stream fairness",
+    "slowest stream p99 us",
+    "cpu/byte vs quinn plaintext",
+];
+
+/// The cell's axes but protocol and crypto, in table order.
+fn axes(cell: &Cell) -> Vec<String> {
+    vec![
+        cell.runtime.to_string(),
+        payload_size(cell).to_string(),
+        inflight(cell).to_string(),
+        cell.gso.to_string(),
+        cell.threads.to_string(),
+        connections(cell).to_string(),
+        cell.workload.streams().to_string(),
+        cell.workload
+            .stream_per_message
+            .map_or(String::new(), |kind| kind.to_string()),
+    ]
+}
+
+fn cells(
+    rows: &[(Cell, io::Result<Measured>)],
+    cell: &Cell,
+    res: &io::Result<Measured>,
+) -> Vec<String> {
+    let mut row = vec![cell.proto.to_string(), crypto(cell)];
+    row.extend(axes(cell));
+    match res {
+        Ok(m) => row.extend(columns(m, baseline(rows, cell))),
+        Err(e) => {
+            row.push(format!("failed: {}", e));
+            row.resize(HEADER.len(), String::new());
+        }
+    }
+    row
+}
+
+fn write_markdown(out: &mut dyn Write, rows: &[(Cell, io::Result<Measured>)]) -> io::Result<()> {
+    writeln!(out, "| {} |", HEADER.join(" | "))?;
+    writeln!(out, "|{}", "---|".repeat(HEADER.len()))?;
+    for (cell, res) in rows {
+        writeln!(out, "| {} |", cells(rows, cell, res).join(" | "))?;
+    }
+    Ok(())
+}
+
+fn write_csv(out: &mut dyn Write, rows: &[(Cell, io::Result<Measured>)]) -> io::Result<()> {
+    let header: Vec<_> = HEADER.iter().map(|h| h.replace(' ', "_")).collect();
+    writeln!(out, "{}", header.join(","))?;
+    for (cell, res) in rows {
+        let row: Vec<_> = cells(rows, cell, res)
+            .iter()
+            .map(|c| c.replace(',', ";"))
+            .collect();
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
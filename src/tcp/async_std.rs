@@ -0,0 +1,65 @@
+use std::io;
+use std::net::{Shutdown, SocketAddr};
+
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::{TcpListener, TcpStream};
+
+use crate::{Delivery, Listener, Transport, TransportOpt};
+
+/// TCP on the async-std reactor.
+pub struct AsyncStdTcp {
+    stream: TcpStream,
+}
+
+pub struct AsyncStdTcpListener {
+    listener: TcpListener,
+    nodelay: bool,
+}
+
+impl Transport for AsyncStdTcp {
+    type Listener = AsyncStdTcpListener;
+
+    const DELIVERY: Delivery = Delivery::Stream;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<AsyncStdTcpListener> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(AsyncStdTcpListener {
+            listener,
+            nodelay: opt.nodelay,
+        })
+    }
+
+    async fn connect(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(opt.nodelay)?;
+        Ok(Self { stream })
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.stream.write_all(buf).await
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf).await
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.stream.shutdown(Shutdown::Write)?;
+        async_std::io::copy(&mut self.stream, &mut async_std::io::sink()).await?;
+        Ok(())
+    }
+}
+
+impl Listener for AsyncStdTcpListener {
+    type Conn = AsyncStdTcp;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    async fn accept(&mut self) -> io::Result<AsyncStdTcp> {
+        let (stream, _) = self.listener.accept().await?;
+        stream.set_nodelay(self.nodelay)?;
+        Ok(AsyncStdTcp { stream })
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+
+use log::debug;
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token};
+use slab::Slab;
+
+use crate::{Delivery, Listener, Transport, TransportOpt};
+
+// Connections are identified by their slab key, the listener by a token no
+// key reaches.
+const LISTENER: Token = Token(usize::MAX);
+
+/// Non-blocking TCP driven by its own mio event loop.
+///
+/// A server hands out a single session that accepts and serves every
+/// connection on one thread: `recv` reads from whichever connection is
+/// readable, taking them in turn, and `send` writes back to the one it read
+/// from. Connections the peer closes are closed in turn and never end the
+/// session.
+pub struct MioTcp {
+    poll: Poll,
+    events: Events,
+    /// Set on a server's session.
+    listener: Option<TcpListener>,
+    conns: Slab<TcpStream>,
+    /// Connections that may have something to read, readiness is only
+    /// reported when it changes.
+    readable: VecDeque<usize>,
+    /// Connection the last `recv` read from.
+    current: usize,
+    nodelay: bool,
+}
+
+pub struct MioTcpListener {
+    listener: Option<TcpListener>,
+    local_addr: SocketAddr,
+    nodelay: bool,
+}
+
+impl MioTcp {
+    fn new(listener: Option<TcpListener>, nodelay: bool) -> io::Result<Self> {
+        let mut session = Self {
+            poll: Poll::new()?,
+            events: Events::with_capacity(1024),
+            listener,
+            conns: Slab::new(),
+            readable: VecDeque::new(),
+            current: 0,
+            nodelay,
+        };
+        if let Some(listener) = &mut session.listener {
+            session
+                .poll
+                .registry()
+                .register(listener, LISTENER, Interest::READABLE)?;
+        }
+        Ok(session)
+    }
+
+    fn add(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        let entry = self.conns.vacant_entry();
+        let key = entry.key();
+        self.poll.registry().register(
+            &mut stream,
+            Token(key),
+            Interest::READABLE.add(Interest::WRITABLE),
+        )?;
+        entry.insert(stream);
+        // Data that arrived before registering is not reported.
+        self.readable.push_back(key);
+        Ok(())
+    }
+
+    /// Block until something happens, queue the connections that became
+    /// readable and accept new ones.
+    fn wait(&mut self) -> io::Result<()> {
+        loop {
+            match self.poll.poll(&mut self.events, None) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => res?,
+            }
+            break;
+        }
+        let mut accept = false;
+        for event in &self.events {
+            match event.token() {
+                LISTENER => accept = true,
+                Token(key) if event.is_readable() || event.is_read_closed() => {
+                    self.readable.push_back(key)
+                }
+                _ => {}
+            }
+        }
+        if accept {
+            self.accept()?;
+        }
+        Ok(())
+    }
+
+    fn accept(&mut self) -> io::Result<()> {
+        loop {
+            let Some(listener) = &self.listener else {
+                return Ok(());
+            };
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    debug!("accepted {}", peer);
+                    self.add(stream)?;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Close a server's connection the peer is done with, or that failed.
+    fn drop_conn(&mut self, key: usize) {
+        let mut stream = self.conns.remove(key);
+        let _ = stream.shutdown(Shutdown::Write);
+        let _ = self.poll.registry().deregister(&mut stream);
+    }
+}
+
+impl Transport for MioTcp {
+    type Listener = MioTcpListener;
+
+    const DELIVERY: Delivery = Delivery::Stream;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<MioTcpListener> {
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener);
+        Ok(MioTcpListener {
+            local_addr: listener.local_addr()?,
+            listener: Some(listener),
+            nodelay: opt.nodelay,
+        })
+    }
+
+    async fn connect(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self> {
+        // Connect blocking, so the stream is established once registered.
+        let stream = std::net::TcpStream::connect(addr)?;
+        stream.set_nonblocking(true)?;
+        let mut session = Self::new(None, opt.nodelay)?;
+        session.add(TcpStream::from_std(stream))?;
+        Ok(session)
+    }
+
+    async fn send(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let Some(stream) = self.conns.get_mut(self.current) else {
+                return Err(io::ErrorKind::NotConnected.into());
+            };
+            match stream.write(buf) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => buf = &buf[n..],
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.wait()?,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if self.listener.is_some() => {
+                    // The peer is gone, the session serves the others.
+                    debug!("connection error: {}", e);
+                    self.drop_conn(self.current);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            while let Some(&key) = self.readable.front() {
+                let Some(stream) = self.conns.get_mut(key) else {
+                    // Closed since it was queued.
+                    self.readable.pop_front();
+                    continue;
+                };
+                match stream.read(buf) {
+                    Ok(0) if self.listener.is_none() => return Ok(0),
+                    Ok(0) => {
+                        self.readable.pop_front();
+                        self.drop_conn(key);
+                    }
+                    Ok(n) => {
+                        // Let the others read before this one again.
+                        self.readable.rotate_left(1);
+                        self.current = key;
+                        return Ok(n);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        self.readable.pop_front();
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) if self.listener.is_some() => {
+                        debug!("connection error: {}", e);
+                        self.readable.pop_front();
+                        self.drop_conn(key);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            self.wait()?;
+        }
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        for (_, stream) in &self.conns {
+            stream.shutdown(Shutdown::Write)?;
+        }
+        let mut buf = vec![0; 1 << 16];
+        while self.recv(&mut buf).await? != 0 {}
+        Ok(())
+    }
+}
+
+impl Listener for MioTcpListener {
+    type Conn = MioTcp;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    async fn accept(&mut self) -> io::Result<MioTcp> {
+        match self.listener.take() {
+            Some(listener) => MioTcp::new(Some(listener), self.nodelay),
+            None => std::future::pending().await,
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! TCP echo, a connection per client.
+//!
+//! Native servers start a thread per connection, mio serves them all from
+//! one event loop and the async runtimes run a task per connection.
+
+mod async_std;
+mod mio;
+mod monoio;
+mod native;
+mod tokio;
+
+pub use self::async_std::{AsyncStdTcp, AsyncStdTcpListener};
+pub use self::mio::{MioTcp, MioTcpListener};
+pub use self::monoio::{MonoioTcp, MonoioTcpListener};
+pub use self::native::{NativeTcp, NativeTcpListener};
+pub use self::tokio::{TokioTcp, TokioTcpListener};
@@ -0,0 +1,93 @@
+use std::io;
+use std::net::SocketAddr;
+
+use monoio::buf::IoBufMut;
+use monoio::io::{AsyncReadRent, AsyncWriteRent, AsyncWriteRentExt};
+use monoio::net::{TcpListener, TcpStream};
+
+use crate::{Delivery, Listener, Transport, TransportOpt};
+
+/// TCP on monoio, io_uring needs owned buffers so payloads go through `buf`.
+pub struct MonoioTcp {
+    stream: TcpStream,
+    buf: Option<Vec<u8>>,
+}
+
+pub struct MonoioTcpListener {
+    listener: TcpListener,
+    nodelay: bool,
+}
+
+impl MonoioTcp {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            buf: Some(Vec::new()),
+        }
+    }
+}
+
+impl Transport for MonoioTcp {
+    type Listener = MonoioTcpListener;
+
+    const DELIVERY: Delivery = Delivery::Stream;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<MonoioTcpListener> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(MonoioTcpListener {
+            listener,
+            nodelay: opt.nodelay,
+        })
+    }
+
+    async fn connect(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(opt.nodelay)?;
+        Ok(Self::new(stream))
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        let mut owned = self.buf.take().unwrap_or_default();
+        owned.clear();
+        owned.extend_from_slice(buf);
+        let (res, owned) = self.stream.write_all(owned).await;
+        self.buf.replace(owned);
+        res?;
+        Ok(())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut owned = self.buf.take().unwrap_or_default();
+        owned.clear();
+        owned.reserve(buf.len());
+        // No more than `buf` takes, the rest stays in the socket.
+        let (n, owned) = self.stream.read(owned.slice_mut(..buf.len())).await;
+        let owned = owned.into_inner();
+        if let Ok(n) = n {
+            buf[..n].copy_from_slice(&owned[..n]);
+        }
+        self.buf.replace(owned);
+        n
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.stream.shutdown().await?;
+        let mut buf = vec![0; 1 << 16];
+        while self.recv(&mut buf).await? != 0 {}
+        Ok(())
+    }
+}
+
+impl Listener for MonoioTcpListener {
+    type Conn = MonoioTcp;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    async fn accept(&mut self) -> io::Result<MonoioTcp> {
+        let (stream, _) = self.listener.accept().await?;
+        stream.set_nodelay(self.nodelay)?;
+        Ok(MonoioTcp::new(stream))
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+
+use crate::{Delivery, Listener, Transport, TransportOpt};
+
+/// Blocking TCP from the standard library.
+pub struct NativeTcp {
+    stream: TcpStream,
+}
+
+pub struct NativeTcpListener {
+    listener: TcpListener,
+    nodelay: bool,
+}
+
+impl Transport for NativeTcp {
+    type Listener = NativeTcpListener;
+
+    const DELIVERY: Delivery = Delivery::Stream;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<NativeTcpListener> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(NativeTcpListener {
+            listener,
+            nodelay: opt.nodelay,
+        })
+    }
+
+    async fn connect(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(opt.nodelay)?;
+        Ok(Self { stream })
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.stream.write_all(buf)
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.stream.shutdown(Shutdown::Write)?;
+        io::copy(&mut self.stream, &mut io::sink())?;
+        Ok(())
+    }
+}
+
+impl Listener for NativeTcpListener {
+    type Conn = NativeTcp;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    async fn accept(&mut self) -> io::Result<NativeTcp> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nodelay(self.nodelay)?;
+        Ok(NativeTcp { stream })
+    }
+}
@@ -0,0 +1,65 @@
+use std::io;
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::{Delivery, Listener, Transport, TransportOpt};
+
+/// TCP on the tokio reactor.
+pub struct TokioTcp {
+    stream: TcpStream,
+}
+
+pub struct TokioTcpListener {
+    listener: TcpListener,
+    nodelay: bool,
+}
+
+impl Transport for TokioTcp {
+    type Listener = TokioTcpListener;
+
+    const DELIVERY: Delivery = Delivery::Stream;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<TokioTcpListener> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(TokioTcpListener {
+            listener,
+            nodelay: opt.nodelay,
+        })
+    }
+
+    async fn connect(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(opt.nodelay)?;
+        Ok(Self { stream })
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.stream.write_all(buf).await
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf).await
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.stream.shutdown().await?;
+        tokio::io::copy(&mut self.stream, &mut tokio::io::sink()).await?;
+        Ok(())
+    }
+}
+
+impl Listener for TokioTcpListener {
+    type Conn = TokioTcp;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    async fn accept(&mut self) -> io::Result<TokioTcp> {
+        let (stream, _) = self.listener.accept().await?;
+        stream.set_nodelay(self.nodelay)?;
+        Ok(TokioTcp { stream })
+    }
+}
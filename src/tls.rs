@@ -0,0 +1,280 @@
+//! TLS 1.3 over TCP with rustls, the encrypted baseline for QUIC.
+//!
+//! [`TlsStream`] wraps a TCP [`Transport`] of any runtime and runs rustls on
+//! top of its `send` and `recv`, so the same runtimes can be compared with
+//! and without encryption. The handshake happens on a client's `connect` and
+//! on the first `recv` of a server's connection. quinn encrypts with the
+//! same settings under `--crypto tls`.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use clap::ValueEnum;
+use log::debug;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{
+    Certificate, ClientConfig, ClientConnection, Connection, KeyLog, PrivateKey, ServerConfig,
+    ServerConnection, ServerName, SupportedCipherSuite,
+};
+
+use crate::tcp::{AsyncStdTcp, MonoioTcp, NativeTcp, TokioTcp};
+use crate::{Delivery, Listener, Transport, TransportOpt};
+
+pub type AsyncStdTls = TlsStream<AsyncStdTcp>;
+pub type MonoioTls = TlsStream<MonoioTcp>;
+pub type NativeTls = TlsStream<NativeTcp>;
+pub type TokioTls = TlsStream<TokioTcp>;
+
+/// Largest plaintext of one TLS record.
+const RECORD: usize = 1 << 14;
+
+/// Ciphertext read from the socket at once.
+const READ_BUF: usize = 1 << 16;
+
+/// TLS 1.3 cipher suites.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherSuite {
+    Aes128Gcm,
+    Aes256Gcm,
+    Chacha20Poly1305,
+}
+
+impl CipherSuite {
+    fn rustls(self) -> SupportedCipherSuite {
+        match self {
+            CipherSuite::Aes128Gcm => rustls::cipher_suite::TLS13_AES_128_GCM_SHA256,
+            CipherSuite::Aes256Gcm => rustls::cipher_suite::TLS13_AES_256_GCM_SHA384,
+            CipherSuite::Chacha20Poly1305 => rustls::cipher_suite::TLS13_CHACHA20_POLY1305_SHA256,
+        }
+    }
+}
+
+impl fmt::Display for CipherSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+/// The suites both sides offer, in order of preference.
+fn cipher_suites(opt: &TransportOpt) -> Vec<SupportedCipherSuite> {
+    match opt.cipher_suites.as_slice() {
+        [] => rustls::DEFAULT_CIPHER_SUITES.to_vec(),
+        suites => suites.iter().map(|s| s.rustls()).collect(),
+    }
+}
+
+/// Server settings with the certificate and key of `opt`.
+pub(crate) fn server_config(opt: &TransportOpt) -> io::Result<ServerConfig> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&opt.cert_file)?))
+        .map(|cert| cert.map(|cert| Certificate(cert.to_vec())))
+        .collect::<io::Result<Vec<_>>>()?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(&opt.key_file)?))?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no private key"))?;
+    ServerConfig::builder()
+        .with_cipher_suites(&cipher_suites(opt))
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(certs, PrivateKey(key.secret_der().to_vec()))
+        .map_err(io::Error::other)
+}
+
+/// Client settings that accept whatever certificate the server presents.
+pub(crate) fn client_config(opt: &TransportOpt) -> io::Result<ClientConfig> {
+    let mut config = ClientConfig::builder()
+        .with_cipher_suites(&cipher_suites(opt))
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(io::Error::other)?
+        .with_custom_certificate_verifier(Arc::new(SkipVerify))
+        .with_no_client_auth();
+    if let Some(path) = &opt.keylog_file {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        config.key_log = Arc::new(KeyLogFile(Mutex::new(file)));
+    }
+    Ok(config)
+}
+
+/// Secrets in the NSS key log format, for decrypting captures.
+struct KeyLogFile(Mutex<File>);
+
+impl KeyLog for KeyLogFile {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        };
+        let mut file = self.0.lock().expect("key log poisoned");
+        let _ = writeln!(file, "{} {} {}", label, hex(client_random), hex(secret));
+    }
+}
+
+/// Benchmarks run against a self-signed certificate.
+struct SkipVerify;
+
+impl ServerCertVerifier for SkipVerify {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Every message goes out as one write already, Nagle's algorithm would
+/// only hold back the tail of an echo until the peer's delayed ACK.
+fn nodelay(opt: &TransportOpt) -> TransportOpt {
+    TransportOpt {
+        nodelay: true,
+        ..opt.clone()
+    }
+}
+
+/// A TLS session over a stream transport.
+pub struct TlsStream<T> {
+    inner: T,
+    conn: Connection,
+    /// Ciphertext received, rustls takes it from `pos` on.
+    raw: Vec<u8>,
+    pos: usize,
+    len: usize,
+    /// Ciphertext waiting to be sent.
+    out: Vec<u8>,
+}
+
+pub struct TlsListener<T: Transport> {
+    inner: T::Listener,
+    config: Arc<ServerConfig>,
+}
+
+impl<T: Transport> TlsStream<T> {
+    fn new(inner: T, conn: impl Into<Connection>) -> Self {
+        Self {
+            inner,
+            conn: conn.into(),
+            raw: vec![0; READ_BUF],
+            pos: 0,
+            len: 0,
+            out: Vec::new(),
+        }
+    }
+
+    /// Send whatever rustls has to send.
+    async fn flush(&mut self) -> io::Result<()> {
+        while self.conn.wants_write() {
+            self.conn.write_tls(&mut self.out)?;
+        }
+        if !self.out.is_empty() {
+            self.inner.send(&self.out).await?;
+            self.out.clear();
+        }
+        Ok(())
+    }
+
+    /// Hand more ciphertext to rustls and answer what it asks to, false once
+    /// the peer closed the stream.
+    async fn feed(&mut self) -> io::Result<bool> {
+        if self.pos == self.len {
+            self.len = self.inner.recv(&mut self.raw).await?;
+            self.pos = 0;
+            if self.len == 0 {
+                return Ok(false);
+            }
+        }
+        self.pos += self.conn.read_tls(&mut &self.raw[self.pos..self.len])?;
+        let res = self.conn.process_new_packets();
+        // Handshake messages, or the alert telling the peer what went wrong.
+        self.flush().await?;
+        res.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(true)
+    }
+
+    async fn handshake(&mut self) -> io::Result<()> {
+        while self.conn.is_handshaking() {
+            self.flush().await?;
+            if self.conn.is_handshaking() && !self.feed().await? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        self.flush().await
+    }
+}
+
+impl<T: Transport> Transport for TlsStream<T> {
+    type Listener = TlsListener<T>;
+
+    const DELIVERY: Delivery = Delivery::Stream;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<TlsListener<T>> {
+        let config = Arc::new(server_config(opt)?);
+        let inner = T::bind(addr, &nodelay(opt)).await?;
+        Ok(TlsListener { inner, config })
+    }
+
+    async fn connect(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self> {
+        let config = Arc::new(client_config(opt)?);
+        let name = ServerName::try_from("localhost").expect("valid server name");
+        let conn = ClientConnection::new(config, name).map_err(io::Error::other)?;
+        let mut stream = Self::new(T::connect(addr, &nodelay(opt)).await?, conn);
+        stream.handshake().await?;
+        if let Some(suite) = stream.conn.negotiated_cipher_suite() {
+            debug!("negotiated {:?}", suite.suite());
+        }
+        Ok(stream)
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        for record in buf.chunks(RECORD) {
+            self.conn.writer().write_all(record)?;
+            while self.conn.wants_write() {
+                self.conn.write_tls(&mut self.out)?;
+            }
+        }
+        self.flush().await
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.conn.reader().read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+            if !self.feed().await? {
+                // Closed without close_notify.
+                return Ok(0);
+            }
+        }
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.conn.send_close_notify();
+        self.flush().await?;
+        self.inner.close().await
+    }
+}
+
+impl<T: Transport> Listener for TlsListener<T> {
+    type Conn = TlsStream<T>;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    async fn accept(&mut self) -> io::Result<TlsStream<T>> {
+        let conn = ServerConnection::new(self.config.clone()).map_err(io::Error::other)?;
+        Ok(TlsStream::new(self.inner.accept().await?, conn))
+    }
+}
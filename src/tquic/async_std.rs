@@ -0,0 +1,66 @@
+//! The UDP socket of tquic on the async-std reactor.
+
+use std::io;
+use std::net::SocketAddr;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use async_io::Async;
+
+use super::QuicIo;
+use crate::TransportOpt;
+
+/// UDP socket wrapper for QUIC on the async-std reactor.
+pub struct QuicSocket {
+    /// The underlying UDP socket for QUIC Endpoint, in non-blocking mode.
+    socket: Async<UdpSocket>,
+
+    /// Local address of the socket.
+    local_addr: SocketAddr,
+}
+
+impl QuicIo for QuicSocket {
+    async fn bind(local: SocketAddr, _opt: &TransportOpt) -> io::Result<Self> {
+        let socket = Async::<UdpSocket>::bind(local)?;
+        let local_addr = socket.get_ref().local_addr()?;
+
+        Ok(Self { socket, local_addr })
+    }
+
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn recv_timeout(
+        &self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<(usize, SocketAddr, SocketAddr)>> {
+        loop {
+            match timeout {
+                Some(timeout) => {
+                    match async_std::io::timeout(timeout, self.socket.readable()).await {
+                        Err(e) if e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+                        res => res?,
+                    }
+                }
+                None => self.socket.readable().await?,
+            }
+
+            match self.try_recv_from(buf) {
+                Ok(v) => return Ok(Some(v)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, SocketAddr)> {
+        let (len, remote) = self.socket.get_ref().recv_from(buf)?;
+        Ok((len, self.local_addr, remote))
+    }
+
+    fn try_send_to(&self, buf: &[u8], dst: SocketAddr) -> io::Result<usize> {
+        self.socket.get_ref().send_to(buf, dst)
+    }
+}
@@ -0,0 +1,181 @@
+//! The UDP socket of tquic on a mio event loop of its own, sending and
+//! receiving in batches with sendmmsg/recvmmsg and UDP_SEGMENT/UDP_GRO.
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::AsRawFd;
+use std::time::Duration;
+
+use log::warn;
+use mio::net::UdpSocket;
+use mio::{Events, Interest, Poll, Token};
+
+use tquic::PacketInfo;
+
+use super::{send_each, QuicIo};
+use crate::batch::{self, Batch};
+use crate::gso::{self, GroReader};
+use crate::TransportOpt;
+
+const UDP_SOCKET: Token = Token(0);
+
+/// UDP socket wrapper for QUIC, polled by its own mio event loop.
+pub struct QuicSocket {
+    /// The underlying UDP socket for QUIC Endpoint.
+    socket: UdpSocket,
+
+    /// Event poll.
+    poll: RefCell<Poll>,
+
+    /// Storage for events.
+    events: RefCell<Events>,
+
+    /// Local address of the socket.
+    local_addr: SocketAddr,
+
+    /// Datagrams per sendmmsg/recvmmsg, 1 for plain send_to/recv_from.
+    batch: usize,
+
+    /// Send with UDP_SEGMENT, until the kernel refuses.
+    gso: Cell<bool>,
+
+    /// Set when receiving with UDP_GRO.
+    gro: Option<RefCell<GroReader>>,
+}
+
+impl QuicIo for QuicSocket {
+    async fn bind(local: SocketAddr, opt: &TransportOpt) -> io::Result<Self> {
+        let mut socket = UdpSocket::bind(local)?;
+        let local_addr = socket.local_addr()?;
+        let fd = socket.as_raw_fd();
+        let gro = (opt.gso && gso::enable_gro(fd)).then(|| RefCell::new(GroReader::new()));
+        let gso = opt.gso && gso::gso_supported(fd);
+        if opt.gso && (gro.is_none() || !gso) {
+            warn!("segmentation offload unsupported, one datagram per syscall");
+        }
+        let poll = Poll::new()?;
+        poll.registry()
+            .register(&mut socket, UDP_SOCKET, Interest::READABLE)?;
+
+        Ok(Self {
+            socket,
+            poll: RefCell::new(poll),
+            events: RefCell::new(Events::with_capacity(1024)),
+            local_addr,
+            batch: opt.batch.into(),
+            gso: Cell::new(gso),
+            gro,
+        })
+    }
+
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn recv_timeout(
+        &self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<(usize, SocketAddr, SocketAddr)>> {
+        loop {
+            // Readiness is edge triggered, only poll again once the queue is drained.
+            match self.try_recv_from(buf) {
+                Ok(v) => return Ok(Some(v)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+
+            let mut events = self.events.borrow_mut();
+            match self.poll.borrow_mut().poll(&mut events, timeout) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+                Ok(()) if events.is_empty() => return Ok(None),
+                Ok(()) => {}
+            }
+        }
+    }
+
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, SocketAddr)> {
+        let (len, remote) = match &self.gro {
+            Some(gro) => gro.borrow_mut().recv_from(self.socket.as_raw_fd(), buf)?,
+            None => self.socket.recv_from(buf)?,
+        };
+        Ok((len, self.local_addr, remote))
+    }
+
+    fn try_send_to(&self, buf: &[u8], dst: SocketAddr) -> io::Result<usize> {
+        self.socket.send_to(buf, dst)
+    }
+
+    fn try_recv_batch(&self, batch: &mut Batch) -> io::Result<()> {
+        if self.gro.is_some() {
+            batch.clear();
+            while !batch.is_full() {
+                match self.try_recv_from(batch.spare()) {
+                    Ok((len, _, remote)) => batch.filled(len, Some(remote)),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock && !batch.is_empty() => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            return Ok(());
+        }
+        match self.batch {
+            1 => {
+                batch.clear();
+                let (len, remote) = self.socket.recv_from(batch.spare())?;
+                batch.filled(len, Some(remote));
+                Ok(())
+            }
+            max => batch::recv_mmsg(self.socket.as_raw_fd(), batch, max),
+        }
+    }
+
+    fn try_send_batch(&self, pkts: &[(Vec<u8>, PacketInfo)]) -> io::Result<usize> {
+        if self.gso.get() {
+            return self.send_segments(pkts);
+        }
+        if self.batch == 1 {
+            return send_each(self, pkts);
+        }
+        let mut sent = 0;
+        for chunk in pkts.chunks(self.batch) {
+            let pkts = chunk.iter().map(|(pkt, info)| (&pkt[..], info.dst));
+            match batch::send_mmsg(self.socket.as_raw_fd(), pkts) {
+                Ok(n) if n == chunk.len() => sent += n,
+                Ok(n) => return Ok(sent + n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(sent),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(sent)
+    }
+}
+
+impl QuicSocket {
+    /// Send each run of packets to the same destination with UDP_SEGMENT,
+    /// one packet per syscall from the first refusal on.
+    fn send_segments(&self, pkts: &[(Vec<u8>, PacketInfo)]) -> io::Result<usize> {
+        let mut sent = 0;
+        while sent < pkts.len() {
+            let dst = pkts[sent].1.dst;
+            let run: Vec<&[u8]> = pkts[sent..]
+                .iter()
+                .take_while(|(_, info)| info.dst == dst)
+                .map(|(pkt, _)| &pkt[..])
+                .collect();
+            match gso::send_segments(self.socket.as_raw_fd(), &run, dst) {
+                Ok(n) if n == run.len() => sent += n,
+                Ok(n) => return Ok(sent + n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(sent),
+                Err(e) if gso::refused(&e) => {
+                    warn!("UDP_SEGMENT refused, one packet per syscall: {}", e);
+                    self.gso.set(false);
+                    return Ok(sent + self.try_send_batch(&pkts[sent..])?);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(sent)
+    }
+}
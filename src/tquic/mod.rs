@@ -0,0 +1,901 @@
+//! QUIC over tquic.
+//!
+//! tquic is callback driven: the endpoint reports connection and stream events
+//! to a [`TransportHandler`] while its owner pumps packets in and out of the
+//! socket. The handler here buffers those events per connection, so that
+//! [`TquicConn`] can expose them through [`Transport::send`] and
+//! [`Transport::recv`]. The runtime specific part is only the UDP socket, see
+//! [`QuicIo`].
+
+mod async_std;
+mod mio;
+mod monoio;
+mod native;
+mod tokio;
+
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::{pin, Pin};
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use bytes::{Buf, Bytes, BytesMut};
+use log::{debug, error};
+use rustc_hash::FxHashMap;
+use tquic::{
+    Config, Connection, Endpoint, Error, PacketInfo, PacketSendHandler, TlsConfig,
+    TransportHandler, TIMER_GRANULARITY,
+};
+
+use crate::batch::Batch;
+use crate::transport::unspecified_for;
+use crate::{Delivery, Listener, StreamKind, Transport, TransportOpt};
+
+pub use self::async_std::QuicSocket as AsyncStdSocket;
+pub use self::mio::QuicSocket as MioSocket;
+pub use self::monoio::QuicSocket as MonoioSocket;
+pub use self::native::QuicSocket as NativeSocket;
+pub use self::tokio::QuicSocket as TokioSocket;
+
+pub type AsyncStdTquic = TquicConn<AsyncStdSocket>;
+pub type MioTquic = TquicConn<MioSocket>;
+pub type MonoioTquic = TquicConn<MonoioSocket>;
+pub type NativeTquic = TquicConn<NativeSocket>;
+pub type TokioTquic = TquicConn<TokioSocket>;
+
+const ALPN: &[u8] = b"http/0.9";
+
+const MAX_BUF_SIZE: usize = 65536;
+
+const UDP_PAYLOAD_SIZE: usize = 1460;
+
+/// Bytes of a stream read ahead of `recv`. The rest stays with tquic, whose
+/// flow control then holds the peer back.
+const RX_LIMIT: usize = 4 * MAX_BUF_SIZE;
+
+/// The first client initiated bidirectional stream.
+const STREAM_ID: u64 = 0;
+
+/// Streams of one kind and side are numbered 4 apart: 0, 4, 8 and so on for
+/// the client's bidirectional ones, from 2 for its unidirectional ones and
+/// from 3 for the server's.
+const STREAM_ID_STEP: u64 = 4;
+
+/// Bit set in the ids of unidirectional streams.
+const STREAM_ID_UNI: u64 = 2;
+
+/// Bit set in the ids of streams a server opened.
+const STREAM_ID_SERVER: u64 = 1;
+
+/// UDP socket a tquic endpoint sends and receives packets through.
+#[allow(async_fn_in_trait)]
+pub trait QuicIo: Sized + 'static {
+    async fn bind(local: SocketAddr, opt: &TransportOpt) -> io::Result<Self>;
+
+    /// Return the local address of the socket.
+    fn local_addr(&self) -> SocketAddr;
+
+    /// Wait up to `timeout` for a datagram, `None` if the timer fired first.
+    async fn recv_timeout(
+        &self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<(usize, SocketAddr, SocketAddr)>>;
+
+    /// Receive a datagram already queued on the socket, `WouldBlock` otherwise.
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, SocketAddr)>;
+
+    /// Send without waiting, `WouldBlock` when the socket buffer is full.
+    fn try_send_to(&self, buf: &[u8], dst: SocketAddr) -> io::Result<usize>;
+
+    /// Receive the datagrams already queued on the socket into `batch`,
+    /// `WouldBlock` if there are none. One at a time unless overridden.
+    fn try_recv_batch(&self, batch: &mut Batch) -> io::Result<()> {
+        batch.clear();
+        let (len, _, remote) = self.try_recv_from(batch.spare())?;
+        batch.filled(len, Some(remote));
+        Ok(())
+    }
+
+    /// Send as many of `pkts` as the socket takes without waiting, returning
+    /// how many. One at a time unless overridden.
+    fn try_send_batch(&self, pkts: &[(Vec<u8>, PacketInfo)]) -> io::Result<usize> {
+        send_each(self, pkts)
+    }
+}
+
+/// Send `pkts` one syscall each, until the socket would block.
+fn send_each<S: QuicIo>(sock: &S, pkts: &[(Vec<u8>, PacketInfo)]) -> io::Result<usize> {
+    let mut count = 0;
+    for (pkt, info) in pkts {
+        match sock.try_send_to(pkt, info.dst) {
+            Ok(_) => debug!("written {} bytes", pkt.len()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                debug!("socket send would block");
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Hands the packets tquic produced to the socket.
+struct Sender<S>(Rc<S>);
+
+impl<S: QuicIo> PacketSendHandler for Sender<S> {
+    fn on_packets_send(&self, pkts: &[(Vec<u8>, PacketInfo)]) -> tquic::Result<usize> {
+        self.0
+            .try_send_batch(pkts)
+            .map_err(|e| Error::InvalidOperation(format!("socket send_to(): {:?}", e)))
+    }
+}
+
+/// What the handler learned about one connection.
+#[derive(Default)]
+struct ConnState {
+    streams: FxHashMap<u64, StreamState>,
+
+    /// Bidirectional stream the client opened last.
+    last_stream_id: u64,
+
+    /// Unidirectional streams this side opened.
+    uni_opened: u64,
+
+    /// Unidirectional streams of the server, waiting for a client's `recv`.
+    incoming_uni: VecDeque<u64>,
+
+    /// Number of [`TquicConn`]s on the connection.
+    handles: usize,
+
+    is_server: bool,
+
+    established: bool,
+    closed: bool,
+}
+
+/// What the handler learned about one stream.
+#[derive(Default)]
+struct StreamState {
+    /// Bytes read from the stream and not yet returned by `recv`, up to
+    /// about [`RX_LIMIT`].
+    rx: BytesMut,
+
+    /// Bytes accepted by `send` and not yet taken by the stream.
+    tx: VecDeque<Bytes>,
+
+    finished: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    conns: FxHashMap<u64, ConnState>,
+
+    /// Connections and streams clients opened, waiting for `accept`.
+    accepted: VecDeque<(u64, u64)>,
+}
+
+struct Handler {
+    shared: Rc<RefCell<Shared>>,
+
+    keylog_file: Option<String>,
+
+    qlog_file: Option<String>,
+
+    /// Scratch buffer for stream reads.
+    buf: Vec<u8>,
+}
+
+impl Handler {
+    fn state<'a>(shared: &'a mut Shared, conn: &Connection) -> Option<&'a mut ConnState> {
+        conn.index().and_then(|index| shared.conns.get_mut(&index))
+    }
+}
+
+impl TransportHandler for Handler {
+    fn on_conn_created(&mut self, conn: &mut Connection) {
+        debug!("{} connection is created", conn.trace_id());
+        if let Some(index) = conn.index() {
+            let state = ConnState {
+                is_server: conn.is_server(),
+                ..Default::default()
+            };
+            self.shared.borrow_mut().conns.insert(index, state);
+        }
+
+        if let Some(keylog_file) = &self.keylog_file {
+            match OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(keylog_file)
+            {
+                Ok(file) => conn.set_keylog(Box::new(file)),
+                Err(_) => error!("{} set key log failed", conn.trace_id()),
+            }
+        }
+
+        if let Some(qlog_file) = &self.qlog_file {
+            match OpenOptions::new().create(true).append(true).open(qlog_file) {
+                Ok(qlog) => conn.set_qlog(
+                    Box::new(qlog),
+                    "tquic qlog".into(),
+                    format!("id={}", conn.trace_id()),
+                ),
+                Err(_) => error!("{} set qlog failed", conn.trace_id()),
+            }
+        }
+    }
+
+    fn on_conn_established(&mut self, conn: &mut Connection) {
+        debug!("{} connection is established", conn.trace_id());
+        if let Some(state) = Self::state(&mut self.shared.borrow_mut(), conn) {
+            state.established = true;
+        }
+    }
+
+    fn on_conn_closed(&mut self, conn: &mut Connection) {
+        debug!("{} connection is closed", conn.trace_id());
+        let (Some(index), mut shared) = (conn.index(), self.shared.borrow_mut()) else {
+            return;
+        };
+        let Some(state) = shared.conns.get_mut(&index) else {
+            return;
+        };
+        state.closed = true;
+        if state.handles == 0 {
+            shared.conns.remove(&index);
+        }
+    }
+
+    fn on_stream_created(&mut self, conn: &mut Connection, stream_id: u64) {
+        debug!("{} stream {} is created", conn.trace_id(), stream_id);
+        let (Some(index), mut shared) = (conn.index(), self.shared.borrow_mut()) else {
+            return;
+        };
+        let Some(state) = shared.conns.get_mut(&index) else {
+            return;
+        };
+        state.streams.entry(stream_id).or_default();
+        if (stream_id & STREAM_ID_SERVER != 0) == conn.is_server() {
+            // Opened by a send of this side.
+            return;
+        }
+        if conn.is_server() {
+            shared.accepted.push_back((index, stream_id));
+        } else {
+            // Servers only open streams to answer on.
+            state.incoming_uni.push_back(stream_id);
+        }
+    }
+
+    fn on_stream_readable(&mut self, conn: &mut Connection, stream_id: u64) {
+        let mut shared = self.shared.borrow_mut();
+        let stream = Self::state(&mut shared, conn).and_then(|s| s.streams.get_mut(&stream_id));
+        read_stream(conn, stream_id, stream, &mut self.buf);
+    }
+
+    fn on_stream_writable(&mut self, conn: &mut Connection, stream_id: u64) {
+        debug!("{} stream {} is writable", conn.trace_id(), stream_id);
+        let mut shared = self.shared.borrow_mut();
+        let stream = Self::state(&mut shared, conn).and_then(|s| s.streams.get_mut(&stream_id));
+        let res = match stream {
+            Some(stream) => flush(conn, stream_id, &mut stream.tx),
+            None => conn.stream_want_write(stream_id, false),
+        };
+        if let Err(e) = res {
+            error!("stream send failed {:?}", e);
+        }
+    }
+
+    fn on_stream_closed(&mut self, conn: &mut Connection, stream_id: u64) {
+        debug!("{} stream {} is closed", conn.trace_id(), stream_id);
+    }
+
+    fn on_new_token(&mut self, _conn: &mut Connection, _token: Vec<u8>) {}
+}
+
+/// Read from the stream into its `rx` until that holds [`RX_LIMIT`] bytes,
+/// or everything if the stream was already dropped.
+fn read_stream(
+    conn: &mut Connection,
+    stream_id: u64,
+    mut stream: Option<&mut StreamState>,
+    buf: &mut [u8],
+) {
+    while stream.as_ref().is_none_or(|s| s.rx.len() < RX_LIMIT) {
+        let Ok((read, fin)) = conn.stream_read(stream_id, buf) else {
+            break;
+        };
+        debug!(
+            "{} read {} bytes from stream {}, fin: {}",
+            conn.trace_id(),
+            read,
+            stream_id,
+            fin
+        );
+        if let Some(stream) = &mut stream {
+            stream.rx.extend_from_slice(&buf[..read]);
+            stream.finished |= fin;
+        }
+        if read == 0 {
+            break;
+        }
+    }
+}
+
+/// Move queued bytes into the stream as far as flow control allows, and ask
+/// to be called back when the rest can go.
+fn flush(conn: &mut Connection, stream_id: u64, queue: &mut VecDeque<Bytes>) -> tquic::Result<()> {
+    while let Some(buf) = queue.front_mut() {
+        match conn.stream_write(stream_id, buf.clone(), false) {
+            Ok(written) if written < buf.len() => {
+                buf.advance(written);
+                break;
+            }
+            Ok(_) => {
+                queue.pop_front();
+            }
+            Err(Error::Done) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    conn.stream_want_write(stream_id, !queue.is_empty())
+}
+
+fn to_io(e: Error) -> io::Error {
+    io::Error::other(e)
+}
+
+fn config(opt: &TransportOpt) -> io::Result<Config> {
+    let mut config = Config::new().map_err(to_io)?;
+    config.set_max_idle_timeout(opt.idle_timeout);
+    config.set_send_udp_payload_size(UDP_PAYLOAD_SIZE);
+    config.set_recv_udp_payload_size(UDP_PAYLOAD_SIZE as u16);
+    Ok(config)
+}
+
+/// An endpoint together with its socket, shared by all its connections.
+struct Driver<S> {
+    endpoint: RefCell<Endpoint>,
+    sock: Rc<S>,
+    shared: Rc<RefCell<Shared>>,
+
+    /// Set while one task is doing socket I/O for everybody.
+    pumping: Cell<bool>,
+
+    /// Number of finished I/O rounds.
+    round: Cell<u64>,
+
+    /// Tasks waiting for the current I/O round to finish.
+    waiters: RefCell<Vec<Waker>>,
+
+    /// Set when a stream gave the endpoint something to send, which the
+    /// round waiting on the socket would otherwise sit on.
+    kicked: Cell<bool>,
+
+    /// The task doing the round, woken when kicked.
+    pumper: RefCell<Option<Waker>>,
+
+    /// Datagrams drained from the socket after the first of a round.
+    batch: RefCell<Batch>,
+}
+
+impl<S: QuicIo> Driver<S> {
+    fn new(config: Config, is_server: bool, sock: S, opt: &TransportOpt) -> Rc<Self> {
+        let sock = Rc::new(sock);
+        let shared = Rc::new(RefCell::new(Shared::default()));
+        let handler = Handler {
+            shared: shared.clone(),
+            keylog_file: opt.keylog_file.clone(),
+            qlog_file: opt.qlog_file.clone(),
+            buf: vec![0; MAX_BUF_SIZE],
+        };
+        let endpoint = Endpoint::new(
+            Box::new(config),
+            is_server,
+            Box::new(handler),
+            Rc::new(Sender(sock.clone())),
+        );
+
+        Rc::new(Self {
+            endpoint: RefCell::new(endpoint),
+            sock,
+            shared,
+            pumping: Cell::new(false),
+            round: Cell::new(0),
+            waiters: RefCell::new(Vec::new()),
+            kicked: Cell::new(false),
+            pumper: RefCell::new(None),
+            batch: RefCell::new(Batch::new(opt.batch.into(), MAX_BUF_SIZE)),
+        })
+    }
+
+    /// Make progress on the endpoint. The first caller does the socket I/O,
+    /// concurrent callers wait for it to finish a round and then re-check
+    /// their connection state.
+    async fn pump(&self, buf: &mut [u8]) -> io::Result<()> {
+        if self.pumping.replace(true) {
+            NextRound {
+                driver: self,
+                seen: self.round.get(),
+            }
+            .await;
+            return Ok(());
+        }
+
+        let res = self.poll_io(buf).await;
+        self.pumping.set(false);
+        self.round.set(self.round.get() + 1);
+        for waker in self.waiters.borrow_mut().drain(..) {
+            waker.wake();
+        }
+        res
+    }
+
+    /// Have the current round flush what a stream just gave the endpoint,
+    /// rather than wait for a datagram or a timer first.
+    fn kick(&self) {
+        self.kicked.set(true);
+        if let Some(waker) = self.pumper.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+
+    /// Flush outgoing packets, then process one batch of incoming datagrams or
+    /// the expired timers. A kick ends the round early, so that the next one
+    /// flushes right away.
+    async fn poll_io(&self, buf: &mut [u8]) -> io::Result<()> {
+        self.kicked.set(false);
+        let timeout = {
+            let mut endpoint = self.endpoint.borrow_mut();
+            if let Err(e) = endpoint.process_connections() {
+                error!("process connections error: {:?}", e);
+            }
+            endpoint.timeout().map(|v| cmp::max(v, TIMER_GRANULARITY))
+        };
+
+        let Some(received) = self
+            .unless_kicked(self.sock.recv_timeout(buf, timeout))
+            .await
+        else {
+            return Ok(());
+        };
+        let Some((len, local, remote)) = received? else {
+            self.endpoint.borrow_mut().on_timeout(Instant::now());
+            return Ok(());
+        };
+        self.process(&mut buf[..len], local, remote);
+
+        let mut batch = self.batch.borrow_mut();
+        loop {
+            match self.sock.try_recv_batch(&mut batch) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+            for i in 0..batch.len() {
+                let remote = batch.source(i).unwrap_or(remote);
+                self.process(batch.get_mut(i), local, remote);
+            }
+        }
+    }
+
+    /// The output of `fut`, or `None` if the driver is kicked first.
+    async fn unless_kicked<F: Future>(&self, fut: F) -> Option<F::Output> {
+        let mut fut = pin!(fut);
+        poll_fn(|cx| {
+            if self.kicked.get() {
+                return Poll::Ready(None);
+            }
+            if let Poll::Ready(output) = fut.as_mut().poll(cx) {
+                return Poll::Ready(Some(output));
+            }
+            *self.pumper.borrow_mut() = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Hand one incoming packet to the endpoint.
+    fn process(&self, pkt: &mut [u8], local: SocketAddr, remote: SocketAddr) {
+        debug!("socket recv {} bytes from {:?}", pkt.len(), remote);
+        let pkt_info = PacketInfo {
+            src: remote,
+            dst: local,
+            time: Instant::now(),
+        };
+        if let Err(e) = self.endpoint.borrow_mut().recv(pkt, &pkt_info) {
+            error!("recv failed: {:?}", e);
+        }
+    }
+}
+
+/// Resolves once the driver finished the I/O round after `seen`.
+struct NextRound<'a, S> {
+    driver: &'a Driver<S>,
+    seen: u64,
+}
+
+impl<S> Future for NextRound<'_, S> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.driver.round.get() != self.seen {
+            return Poll::Ready(());
+        }
+        self.driver.waiters.borrow_mut().push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// One stream of a tquic connection, or a pair of unidirectional ones, see
+/// [`StreamKind::Uni`]. A client closes the connection with its last stream,
+/// a server waits for the client to.
+pub struct TquicConn<S> {
+    driver: Rc<Driver<S>>,
+    index: u64,
+
+    /// Stream `send` writes to, a new unidirectional one on the first send
+    /// if `None`.
+    send_id: Option<u64>,
+
+    /// Stream `recv` reads from, the peer's next unidirectional one if `None`.
+    recv_id: Option<u64>,
+
+    buf: Vec<u8>,
+}
+
+pub struct TquicListener<S> {
+    driver: Rc<Driver<S>>,
+    buf: Vec<u8>,
+}
+
+impl<S: QuicIo> TquicConn<S> {
+    fn new(
+        driver: Rc<Driver<S>>,
+        index: u64,
+        send_id: Option<u64>,
+        recv_id: Option<u64>,
+    ) -> io::Result<Self> {
+        {
+            let mut shared = driver.shared.borrow_mut();
+            let state = shared
+                .conns
+                .get_mut(&index)
+                .ok_or(io::ErrorKind::NotConnected)?;
+            state.handles += 1;
+            for stream_id in send_id.into_iter().chain(recv_id) {
+                state.streams.entry(stream_id).or_default();
+            }
+        }
+        Ok(Self {
+            driver,
+            index,
+            send_id,
+            recv_id,
+            buf: vec![0; MAX_BUF_SIZE],
+        })
+    }
+
+    /// The server's side of a stream the client opened, answered on a stream
+    /// of its own if unidirectional.
+    fn accepted(driver: Rc<Driver<S>>, index: u64, stream_id: u64) -> io::Result<Self> {
+        if stream_id & STREAM_ID_UNI != 0 {
+            Self::new(driver, index, None, Some(stream_id))
+        } else {
+            Self::new(driver, index, Some(stream_id), Some(stream_id))
+        }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut ConnState) -> R) -> io::Result<R> {
+        let mut shared = self.driver.shared.borrow_mut();
+        match shared.conns.get_mut(&self.index) {
+            Some(state) => Ok(f(state)),
+            None => Err(io::ErrorKind::NotConnected.into()),
+        }
+    }
+
+    /// Like [`TquicConn::with_state`], for one stream and whether the
+    /// connection closed.
+    fn with_stream<R>(
+        &self,
+        stream_id: u64,
+        f: impl FnOnce(&mut StreamState, bool) -> R,
+    ) -> io::Result<R> {
+        self.with_state(|state| {
+            let closed = state.closed;
+            state.streams.get_mut(&stream_id).map(|s| f(s, closed))
+        })?
+        .ok_or_else(|| io::ErrorKind::NotConnected.into())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.with_state(|state| state.closed).unwrap_or(true)
+    }
+
+    /// The stream to send on, opening a unidirectional one if there is none.
+    fn send_id(&mut self) -> io::Result<u64> {
+        if let Some(stream_id) = self.send_id {
+            return Ok(stream_id);
+        }
+        let stream_id = self.with_state(|state| {
+            let side = if state.is_server { STREAM_ID_SERVER } else { 0 };
+            let stream_id = state.uni_opened * STREAM_ID_STEP + STREAM_ID_UNI + side;
+            state.uni_opened += 1;
+            state.streams.entry(stream_id).or_default();
+            stream_id
+        })?;
+        self.send_id = Some(stream_id);
+        Ok(stream_id)
+    }
+
+    /// Read what the handler left with tquic, once `rx` ran dry. With the
+    /// peer held back by flow control, no packet may come that would call
+    /// the handler again.
+    fn read_ahead(&mut self, stream_id: u64) {
+        let mut shared = self.driver.shared.borrow_mut();
+        let stream = shared
+            .conns
+            .get_mut(&self.index)
+            .and_then(|state| state.streams.get_mut(&stream_id))
+            .filter(|stream| stream.rx.is_empty() && !stream.finished);
+        let Some(stream) = stream else {
+            return;
+        };
+        if let Some(conn) = self.driver.endpoint.borrow_mut().conn_get_mut(self.index) {
+            read_stream(conn, stream_id, Some(&mut *stream), &mut self.buf);
+        }
+        // Reading opened the flow control window, kicking only then keeps
+        // an idle stream from ending every round.
+        if !stream.rx.is_empty() || stream.finished {
+            self.driver.kick();
+        }
+    }
+
+    /// The stream to receive on, waiting for the peer to open a
+    /// unidirectional one if there is none. `None` if the connection closed
+    /// first.
+    async fn recv_id(&mut self) -> io::Result<Option<u64>> {
+        while self.recv_id.is_none() {
+            let (incoming, closed) =
+                self.with_state(|state| (state.incoming_uni.pop_front(), state.closed))?;
+            if incoming.is_some() {
+                self.recv_id = incoming;
+            } else if closed {
+                return Ok(None);
+            } else {
+                self.driver.pump(&mut self.buf).await?;
+            }
+        }
+        Ok(self.recv_id)
+    }
+}
+
+impl<S: QuicIo> Transport for TquicConn<S> {
+    type Listener = TquicListener<S>;
+
+    const DELIVERY: Delivery = Delivery::Stream;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<TquicListener<S>> {
+        let mut config = config(opt)?;
+        // Always TLS, tquic derives its packet keys inside the boringssl
+        // session and can neither skip encryption nor pick the cipher.
+        let tls_config =
+            TlsConfig::new_server_config(&opt.cert_file, &opt.key_file, vec![ALPN.to_vec()], true)
+                .map_err(to_io)?;
+        config.set_tls_config(tls_config);
+
+        let sock = S::bind(addr, opt).await?;
+        Ok(TquicListener {
+            driver: Driver::new(config, true, sock, opt),
+            buf: vec![0; MAX_BUF_SIZE],
+        })
+    }
+
+    async fn connect(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self> {
+        let mut config = config(opt)?;
+        let tls_config = TlsConfig::new_client_config(vec![ALPN.to_vec()], false).map_err(to_io)?;
+        config.set_tls_config(tls_config);
+
+        let sock = S::bind(unspecified_for(&addr), opt).await?;
+        let local = sock.local_addr();
+        let driver = Driver::new(config, false, sock, opt);
+        let index = driver
+            .endpoint
+            .borrow_mut()
+            .connect(local, addr, None, None, None)
+            .map_err(to_io)?;
+
+        let mut conn = Self::new(driver, index, Some(STREAM_ID), Some(STREAM_ID))?;
+        loop {
+            let (established, closed) = conn.with_state(|s| (s.established, s.closed))?;
+            if established {
+                return Ok(conn);
+            }
+            if closed {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    "connection closed during handshake",
+                ));
+            }
+            conn.driver.pump(&mut conn.buf).await?;
+        }
+    }
+
+    async fn open_stream(&mut self, kind: StreamKind) -> io::Result<Self> {
+        match kind {
+            StreamKind::Bi => {
+                let stream_id = self.with_state(|state| {
+                    state.last_stream_id += STREAM_ID_STEP;
+                    state.last_stream_id
+                })?;
+                Self::new(
+                    self.driver.clone(),
+                    self.index,
+                    Some(stream_id),
+                    Some(stream_id),
+                )
+            }
+            StreamKind::Uni => {
+                let mut conn = Self::new(self.driver.clone(), self.index, None, None)?;
+                conn.send_id()?;
+                Ok(conn)
+            }
+        }
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        let stream_id = self.send_id()?;
+        {
+            let mut shared = self.driver.shared.borrow_mut();
+            let stream = shared
+                .conns
+                .get_mut(&self.index)
+                .filter(|state| !state.closed)
+                .and_then(|state| state.streams.get_mut(&stream_id))
+                .ok_or(io::ErrorKind::BrokenPipe)?;
+            stream.tx.push_back(Bytes::copy_from_slice(buf));
+
+            let mut endpoint = self.driver.endpoint.borrow_mut();
+            if let Some(conn) = endpoint.conn_get_mut(self.index) {
+                flush(conn, stream_id, &mut stream.tx).map_err(to_io)?;
+            }
+        }
+        self.driver.kick();
+
+        // Wait until the stream took everything, like a blocking write.
+        while !self.with_stream(stream_id, |s, closed| s.tx.is_empty() || closed)? {
+            self.driver.pump(&mut self.buf).await?;
+        }
+        Ok(())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(stream_id) = self.recv_id().await? else {
+            return Ok(0);
+        };
+        loop {
+            self.read_ahead(stream_id);
+            let read = self.with_stream(stream_id, |s, closed| {
+                if !s.rx.is_empty() {
+                    let n = cmp::min(buf.len(), s.rx.len());
+                    buf[..n].copy_from_slice(&s.rx[..n]);
+                    s.rx.advance(n);
+                    Some(n)
+                } else if s.finished || closed {
+                    Some(0)
+                } else {
+                    None
+                }
+            })?;
+            if let Some(n) = read {
+                return Ok(n);
+            }
+            self.driver.pump(&mut self.buf).await?;
+        }
+    }
+
+    async fn finish(&mut self) -> io::Result<()> {
+        if self.is_closed() {
+            return Ok(());
+        }
+        let stream_id = self.send_id()?;
+        let mut endpoint = self.driver.endpoint.borrow_mut();
+        if let Some(conn) = endpoint.conn_get_mut(self.index) {
+            conn.stream_write(stream_id, Bytes::new(), true)
+                .map_err(to_io)?;
+        }
+        self.driver.kick();
+        Ok(())
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        if self.is_closed() {
+            return Ok(());
+        }
+        self.finish().await?;
+        let mut buf = vec![0; MAX_BUF_SIZE];
+        while self.recv(&mut buf).await? > 0 {}
+        Ok(())
+    }
+
+    /// Pumps until the CONNECTION_CLOSE went out, otherwise the server only
+    /// learns about the close from its idle timeout.
+    async fn close_connection(mut self) -> io::Result<()> {
+        let last = self.with_state(|state| state.handles == 1 && !state.is_server);
+        if !last.unwrap_or(false) {
+            return Ok(());
+        }
+        if let Some(conn) = self.driver.endpoint.borrow_mut().conn_get_mut(self.index) {
+            let _ = conn.close(true, 0, b"");
+        }
+        loop {
+            {
+                let mut endpoint = self.driver.endpoint.borrow_mut();
+                if let Err(e) = endpoint.process_connections() {
+                    error!("process connections error: {:?}", e);
+                }
+                // Draining starts once the close is written into a packet.
+                match endpoint.conn_get_mut(self.index) {
+                    Some(conn) if !conn.is_draining() && !conn.is_closed() => {}
+                    _ => return Ok(()),
+                }
+            }
+            self.driver.pump(&mut self.buf).await?;
+        }
+    }
+}
+
+impl<S> Drop for TquicConn<S> {
+    fn drop(&mut self) {
+        let mut shared = self.driver.shared.borrow_mut();
+        let Some(state) = shared.conns.get_mut(&self.index) else {
+            return;
+        };
+        for stream_id in self.send_id.into_iter().chain(self.recv_id) {
+            state.streams.remove(&stream_id);
+        }
+        state.handles -= 1;
+        if state.handles > 0 {
+            return;
+        }
+        match self.driver.endpoint.borrow_mut().conn_get_mut(self.index) {
+            Some(conn) if !conn.is_server() => {
+                let _ = conn.close(true, 0, b"");
+            }
+            // The client may still open streams.
+            Some(_) if !state.closed => return,
+            _ => {}
+        }
+        shared.conns.remove(&self.index);
+    }
+}
+
+impl<S: QuicIo> Listener for TquicListener<S> {
+    type Conn = TquicConn<S>;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.driver.sock.local_addr())
+    }
+
+    async fn accept(&mut self) -> io::Result<TquicConn<S>> {
+        loop {
+            let accepted = self.driver.shared.borrow_mut().accepted.pop_front();
+            if let Some((index, stream_id)) = accepted {
+                match TquicConn::accepted(self.driver.clone(), index, stream_id) {
+                    Ok(conn) => return Ok(conn),
+                    Err(_) => debug!("connection {} closed before accepting {}", index, stream_id),
+                }
+                continue;
+            }
+            self.driver.pump(&mut self.buf).await?;
+        }
+    }
+}
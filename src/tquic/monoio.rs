@@ -0,0 +1,261 @@
+//! The UDP socket of tquic on monoio's io_uring.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::net::SocketAddr;
+use std::net::UdpSocket as StdUdpSocket;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{ready, Poll, Waker};
+use std::time::Duration;
+
+use log::debug;
+use monoio::buf::IoBufMut;
+use monoio::net::udp::UdpSocket;
+use monoio::BufResult;
+
+use super::{QuicIo, MAX_BUF_SIZE};
+use crate::TransportOpt;
+
+/// Packets queued for sending before `try_send_to` reports `WouldBlock`.
+const SEND_QUEUE: usize = 1024;
+
+type RecvFuture = Pin<Box<dyn Future<Output = BufResult<(usize, SocketAddr), Vec<u8>>>>>;
+
+/// UDP socket wrapper for QUIC on monoio.
+///
+/// io_uring takes owned buffers and completes later, while tquic sends from
+/// a synchronous callback. Sends copy the packet into a queue that a task of
+/// the socket drains in order. A receive stays in flight until it completes,
+/// even when `recv_timeout` times out or is dropped, as cancelling it could
+/// lose the datagram it was about to return. Datagrams that are already
+/// queued in the kernel are read right away through a duplicate of the
+/// socket.
+pub struct QuicSocket {
+    /// The underlying UDP socket for QUIC Endpoint.
+    socket: Rc<UdpSocket>,
+
+    /// The same socket in non-blocking mode, for `try_recv_from`.
+    socket_std: StdUdpSocket,
+
+    /// Local address of the socket.
+    local_addr: SocketAddr,
+
+    /// Receive in flight, started by this or an earlier `recv_timeout`.
+    recv: RefCell<Option<RecvFuture>>,
+
+    /// Receive buffer between receives.
+    buf: RefCell<Option<Vec<u8>>>,
+
+    /// Packets waiting for the send task.
+    queue: Rc<RefCell<SendQueue>>,
+}
+
+#[derive(Default)]
+struct SendQueue {
+    pkts: VecDeque<(Vec<u8>, SocketAddr)>,
+
+    /// Buffers of sent packets, for reuse.
+    free: Vec<Vec<u8>>,
+
+    /// The send task, while it waits for packets.
+    waker: Option<Waker>,
+
+    /// Set once the socket is dropped, the task finishes the queue and exits.
+    closed: bool,
+}
+
+impl QuicSocket {
+    fn start_recv(&self) -> RecvFuture {
+        let socket = self.socket.clone();
+        let mut owned = self.buf.borrow_mut().take().unwrap_or_default();
+        owned.clear();
+        owned.reserve(MAX_BUF_SIZE);
+        Box::pin(async move {
+            let (res, owned) = socket.recv_from(owned.slice_mut(..MAX_BUF_SIZE)).await;
+            (res, owned.into_inner())
+        })
+    }
+}
+
+/// Send the queued packets in order until the socket is dropped.
+async fn send_queued(socket: Rc<UdpSocket>, queue: Rc<RefCell<SendQueue>>) {
+    loop {
+        let next = poll_fn(|cx| {
+            let mut queue = queue.borrow_mut();
+            match queue.pkts.pop_front() {
+                Some(pkt) => Poll::Ready(Some(pkt)),
+                None if queue.closed => Poll::Ready(None),
+                None => {
+                    queue.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+        let Some((pkt, dst)) = next else {
+            return;
+        };
+        let (res, pkt) = socket.send_to(pkt, dst).await;
+        match res {
+            Ok(n) => debug!("written {} bytes", n),
+            // Lost like any datagram, QUIC recovers.
+            Err(e) => debug!("socket send to {}: {}", dst, e),
+        }
+        queue.borrow_mut().free.push(pkt);
+    }
+}
+
+impl QuicIo for QuicSocket {
+    async fn bind(local: SocketAddr, _opt: &TransportOpt) -> io::Result<Self> {
+        let socket_std = StdUdpSocket::bind(local)?;
+        // Needed by the legacy driver and by `try_recv_from`.
+        socket_std.set_nonblocking(true)?;
+        let local_addr = socket_std.local_addr()?;
+        let socket = Rc::new(UdpSocket::from_std(socket_std.try_clone()?)?);
+
+        let queue = Rc::new(RefCell::new(SendQueue::default()));
+        monoio::spawn(send_queued(socket.clone(), queue.clone()));
+
+        Ok(Self {
+            socket,
+            socket_std,
+            local_addr,
+            recv: RefCell::new(None),
+            buf: RefCell::new(Some(Vec::new())),
+            queue,
+        })
+    }
+
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn recv_timeout(
+        &self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<(usize, SocketAddr, SocketAddr)>> {
+        // Polled in place, so that dropping this future leaves it in flight.
+        let recv = poll_fn(|cx| {
+            let mut slot = self.recv.borrow_mut();
+            let recv = slot.get_or_insert_with(|| self.start_recv());
+            let done = ready!(recv.as_mut().poll(cx));
+            *slot = None;
+            Poll::Ready(done)
+        });
+        let (res, owned) = match timeout {
+            Some(timeout) => match monoio::time::timeout(timeout, recv).await {
+                Ok(v) => v,
+                Err(_) => return Ok(None),
+            },
+            None => recv.await,
+        };
+        let res = res.map(|(len, remote)| {
+            buf[..len].copy_from_slice(&owned[..len]);
+            Some((len, self.local_addr, remote))
+        });
+        self.buf.replace(Some(owned));
+        res
+    }
+
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, SocketAddr)> {
+        if self.recv.borrow().is_some() {
+            // The receive in flight gets the next datagram.
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let (len, remote) = self.socket_std.recv_from(buf)?;
+        Ok((len, self.local_addr, remote))
+    }
+
+    fn try_send_to(&self, buf: &[u8], dst: SocketAddr) -> io::Result<usize> {
+        let mut queue = self.queue.borrow_mut();
+        if queue.pkts.len() >= SEND_QUEUE {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let mut pkt = queue.free.pop().unwrap_or_default();
+        pkt.clear();
+        pkt.extend_from_slice(buf);
+        queue.pkts.push_back((pkt, dst));
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+        Ok(buf.len())
+    }
+}
+
+impl Drop for QuicSocket {
+    fn drop(&mut self) {
+        let mut queue = self.queue.borrow_mut();
+        queue.closed = true;
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use super::*;
+    use crate::runtime::Runtime;
+
+    async fn bind() -> QuicSocket {
+        let opt = TransportOpt::default();
+        QuicSocket::bind("127.0.0.1:0".parse().unwrap(), &opt)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn keeps_a_dropped_recv_in_flight() {
+        Runtime::Monoio
+            .block_on(async {
+                let sock = bind().await;
+                let peer = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+                let mut buf = vec![0; MAX_BUF_SIZE];
+                let timeout = Some(Duration::from_millis(10));
+                assert_eq!(sock.recv_timeout(&mut buf, timeout).await.unwrap(), None);
+
+                // The datagram arrives while nobody waits for the receive.
+                {
+                    let mut recv = pin!(sock.recv_timeout(&mut buf, None));
+                    poll_fn(|cx| Poll::Ready(recv.as_mut().poll(cx).is_pending())).await;
+                    peer.send_to(b"one", sock.local_addr()).unwrap();
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                peer.send_to(b"two", sock.local_addr()).unwrap();
+                for msg in [&b"one"[..], b"two"] {
+                    let (len, _, from) = sock.recv_timeout(&mut buf, None).await.unwrap().unwrap();
+                    assert_eq!((&buf[..len], from), (msg, peer.local_addr().unwrap()));
+                }
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn drains_the_send_queue_once_dropped() {
+        let peer = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        let dst = peer.local_addr().unwrap();
+        Runtime::Monoio
+            .block_on(async {
+                let sock = bind().await;
+                for i in 0..100u8 {
+                    sock.try_send_to(&[i], dst).unwrap();
+                }
+                drop(sock);
+                monoio::time::sleep(Duration::from_millis(50)).await;
+            })
+            .unwrap();
+
+        peer.set_nonblocking(true).unwrap();
+        let mut buf = [0; 1];
+        for i in 0..100u8 {
+            peer.recv_from(&mut buf).unwrap();
+            assert_eq!(buf[0], i);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! The UDP socket of tquic from the standard library, blocking with a read
+//! timeout.
+
+use std::io;
+use std::net::SocketAddr;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use super::QuicIo;
+use crate::TransportOpt;
+
+/// UDP socket wrapper for QUIC, blocking with a read timeout.
+pub struct QuicSocket {
+    /// The underlying UDP socket for QUIC Endpoint.
+    socket: UdpSocket,
+
+    /// Local address of the socket.
+    local_addr: SocketAddr,
+}
+
+impl QuicIo for QuicSocket {
+    async fn bind(local: SocketAddr, _opt: &TransportOpt) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local)?;
+        let local_addr = socket.local_addr()?;
+
+        Ok(Self { socket, local_addr })
+    }
+
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn recv_timeout(
+        &self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<(usize, SocketAddr, SocketAddr)>> {
+        self.socket.set_read_timeout(timeout)?;
+        match self.socket.recv_from(buf) {
+            Ok((len, remote)) => Ok(Some((len, self.local_addr, remote))),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The socket is blocking, so every I/O round handles a single datagram.
+    fn try_recv_from(&self, _buf: &mut [u8]) -> io::Result<(usize, SocketAddr, SocketAddr)> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+
+    fn try_send_to(&self, buf: &[u8], dst: SocketAddr) -> io::Result<usize> {
+        self.socket.send_to(buf, dst)
+    }
+}
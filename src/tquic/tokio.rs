@@ -0,0 +1,65 @@
+//! The UDP socket of tquic on the tokio reactor.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use super::QuicIo;
+use crate::TransportOpt;
+
+/// UDP socket wrapper for QUIC on the tokio reactor.
+pub struct QuicSocket {
+    /// The underlying UDP socket for QUIC Endpoint.
+    socket: UdpSocket,
+
+    /// Local address of the socket.
+    local_addr: SocketAddr,
+}
+
+impl QuicIo for QuicSocket {
+    async fn bind(local: SocketAddr, _opt: &TransportOpt) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local).await?;
+        let local_addr = socket.local_addr()?;
+
+        Ok(Self { socket, local_addr })
+    }
+
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn recv_timeout(
+        &self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<(usize, SocketAddr, SocketAddr)>> {
+        loop {
+            match timeout {
+                Some(timeout) => {
+                    match tokio::time::timeout(timeout, self.socket.readable()).await {
+                        Ok(ready) => ready?,
+                        Err(_) => return Ok(None),
+                    }
+                }
+                None => self.socket.readable().await?,
+            }
+
+            match self.try_recv_from(buf) {
+                Ok(v) => return Ok(Some(v)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, SocketAddr)> {
+        let (len, remote) = self.socket.try_recv_from(buf)?;
+        Ok((len, self.local_addr, remote))
+    }
+
+    fn try_send_to(&self, buf: &[u8], dst: SocketAddr) -> io::Result<usize> {
+        self.socket.try_send_to(buf, dst)
+    }
+}
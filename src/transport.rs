@@ -0,0 +1,268 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use clap::{Args, Command, FromArgMatches, ValueEnum};
+
+use crate::batch::Batch;
+use crate::tls::CipherSuite;
+
+/// How a transport frames the bytes handed to [`Transport::send`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Reliable ordered byte stream, a `recv` may return any part of what was sent.
+    Stream,
+    /// Unreliable messages, every `recv` returns exactly one `send`.
+    Datagram,
+}
+
+/// Settings shared by all transports, each protocol only reads what it needs.
+#[derive(Args, Debug, Clone)]
+pub struct TransportOpt {
+    /// TLS certificate in PEM format.
+    #[clap(long = "cert", default_value = "./cert.crt", value_name = "FILE")]
+    pub cert_file: String,
+
+    /// TLS private key in PEM format.
+    #[clap(long = "key", default_value = "./cert.key", value_name = "FILE")]
+    pub key_file: String,
+
+    /// Connection idle timeout in milliseconds.
+    #[clap(long, default_value = "5000", value_name = "TIME")]
+    pub idle_timeout: u64,
+
+    /// Save TLS key log into the given file.
+    #[clap(long, value_name = "FILE")]
+    pub keylog_file: Option<String>,
+
+    /// Send TCP segments right away instead of holding small ones back until
+    /// earlier ones are acknowledged (TCP_NODELAY). Always on with TLS.
+    #[clap(long)]
+    pub nodelay: bool,
+
+    /// TLS 1.3 cipher suites to offer, comma separated in order of
+    /// preference [default: all]. TLS over TCP, and quinn with `--crypto
+    /// tls`.
+    #[clap(long, value_enum, value_delimiter = ',')]
+    pub cipher_suites: Vec<CipherSuite>,
+
+    /// Whether QUIC encrypts its packets [default: plaintext for quinn, tls
+    /// for tquic, which has no other mode].
+    #[clap(long, value_enum)]
+    pub crypto: Option<Crypto>,
+
+    /// Save QUIC qlog into the given file.
+    #[clap(long, value_name = "FILE")]
+    pub qlog_file: Option<String>,
+
+    /// Datagrams per sendmmsg/recvmmsg call, 1 for a syscall per datagram.
+    /// Only UDP and tquic on mio batch, on Linux.
+    #[clap(long, default_value = "1", value_name = "N", value_parser = clap::value_parser!(u16).range(1..=1024))]
+    pub batch: u16,
+
+    /// Send runs of equally sized datagrams with one UDP_SEGMENT syscall and
+    /// receive with UDP_GRO, falling back to a datagram per syscall where
+    /// the kernel refuses. UDP and tquic on mio, and quinn on tokio and
+    /// async-std, on Linux.
+    #[clap(long)]
+    pub gso: bool,
+
+    /// Threads, each with a runtime and socket of its own. Servers bind that
+    /// many SO_REUSEPORT sockets to one port, clients open a flow per
+    /// thread. Only UDP, on Linux.
+    #[clap(long, default_value = "1", value_name = "N", value_parser = clap::value_parser!(u16).range(1..=1024))]
+    pub threads: u16,
+}
+
+impl Default for TransportOpt {
+    /// The values clap would produce for an empty command line.
+    fn default() -> Self {
+        let matches = Self::augment_args(Command::new("transport")).get_matches_from(["transport"]);
+        Self::from_arg_matches(&matches).expect("defaults are valid")
+    }
+}
+
+impl TransportOpt {
+    /// Command line for a server process sharing these options. Key and qlog
+    /// files stay with this process, both sides would write the same file.
+    pub(crate) fn server_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--cert".to_string(),
+            self.cert_file.clone(),
+            "--key".to_string(),
+            self.key_file.clone(),
+            "--idle-timeout".to_string(),
+            self.idle_timeout.to_string(),
+            "--batch".to_string(),
+            self.batch.to_string(),
+            "--threads".to_string(),
+            self.threads.to_string(),
+        ];
+        if self.gso {
+            args.push("--gso".to_string());
+        }
+        if let Some(crypto) = self.crypto {
+            args.extend(["--crypto".to_string(), crypto.to_string()]);
+        }
+        if self.nodelay {
+            args.push("--nodelay".to_string());
+        }
+        if !self.cipher_suites.is_empty() {
+            let suites: Vec<_> = self.cipher_suites.iter().map(|s| s.to_string()).collect();
+            args.extend(["--cipher-suites".to_string(), suites.join(",")]);
+        }
+        args
+    }
+}
+
+/// Packet protection of QUIC connections.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crypto {
+    /// No encryption, to measure the protocol alone.
+    Plaintext,
+    /// TLS 1.3 with the certificate and key of `--cert` and `--key`.
+    Tls,
+}
+
+impl fmt::Display for Crypto {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+/// Direction of the streams [`Transport::open_stream`] opens.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    /// Bidirectional, the peer answers on the same stream.
+    Bi,
+    /// Unidirectional, the peer answers on one of its own, which the first
+    /// `recv` takes in.
+    Uni,
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+/// One established connection of a protocol on a given runtime.
+///
+/// Futures returned by these methods are not required to be `Send`, so monoio
+/// and the single threaded tquic endpoints can implement the trait as well.
+#[allow(async_fn_in_trait)]
+pub trait Transport: Sized {
+    type Listener: Listener<Conn = Self>;
+
+    const DELIVERY: Delivery;
+
+    /// Start listening for connections on `addr`.
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self::Listener>;
+
+    /// Open a connection to a server listening on `addr`.
+    async fn connect(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self>;
+
+    /// Open another stream on the connection of this one. Only QUIC
+    /// multiplexes streams, a server accepts each like a connection and
+    /// answers a unidirectional one on a stream it opens on its first send.
+    async fn open_stream(&mut self, kind: StreamKind) -> io::Result<Self> {
+        let _ = kind;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "one stream per connection",
+        ))
+    }
+
+    /// Send the whole buffer, as a single message for datagram transports.
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()>;
+
+    /// Receive into `buf`, returning 0 once a stream has been closed by the peer.
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Like [`Transport::recv`], but `None` if nothing arrived within
+    /// `timeout`. Only datagram transports, which can lose messages, wait
+    /// for less than forever.
+    async fn recv_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> io::Result<Option<usize>> {
+        let _ = timeout;
+        self.recv(buf).await.map(Some)
+    }
+
+    /// Send every message of `batch`, datagram transports may do so in
+    /// fewer syscalls. Datagrams received into the batch go back to where
+    /// they came from.
+    async fn send_batch(&mut self, batch: &Batch) -> io::Result<()> {
+        for buf in batch.iter() {
+            self.send(buf).await?;
+        }
+        Ok(())
+    }
+
+    /// Receive one or more datagrams into `batch`, replacing what it held.
+    /// It stays empty if `timeout` passed first.
+    async fn recv_batch(&mut self, batch: &mut Batch, timeout: Option<Duration>) -> io::Result<()> {
+        batch.clear();
+        let n = match timeout {
+            Some(timeout) => self.recv_timeout(batch.spare(), timeout).await?,
+            None => Some(self.recv(batch.spare()).await?),
+        };
+        if let Some(n) = n {
+            batch.filled(n, None);
+        }
+        Ok(())
+    }
+
+    /// Finish sending and keep receiving, the peer's `recv` returns 0 once
+    /// it read everything. Only QUIC streams close one direction alone.
+    async fn finish(&mut self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "streams close both directions at once",
+        ))
+    }
+
+    /// Finish sending, then discard whatever the peer still sends until it
+    /// closes as well. Datagram transports have nothing to close.
+    async fn close(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Close the connection of a client, once every stream of it was
+    /// closed and the others dropped. Only a connection that outlives its
+    /// streams has anything left to tell the peer.
+    async fn close_connection(self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Server side of a [`Transport`].
+#[allow(async_fn_in_trait)]
+pub trait Listener {
+    type Conn;
+
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Wait for the next client, datagram listeners yield a single session
+    /// and QUIC listeners every stream a client opens.
+    async fn accept(&mut self) -> io::Result<Self::Conn>;
+}
+
+/// Wildcard address of the same family as `remote`, for client sockets.
+pub(crate) fn unspecified_for(remote: &SocketAddr) -> SocketAddr {
+    let ip = match remote {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    SocketAddr::new(ip, 0)
+}
+
+/// Error returned when a datagram session is used before a peer is known.
+pub(crate) fn no_peer() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "no datagram received yet")
+}
@@ -0,0 +1,256 @@
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::AsRawFd;
+use std::time::{Duration, Instant};
+
+use log::warn;
+use mio::net::UdpSocket;
+use mio::{Events, Interest, Poll, Token};
+
+use crate::batch::{self, Batch};
+use crate::gso::{self, GroReader};
+use crate::transport::{no_peer, unspecified_for};
+use crate::{Delivery, Listener, Transport, TransportOpt};
+
+// A token to allow us to identify which event is for the `UdpSocket`.
+const UDP_SOCKET: Token = Token(0);
+
+/// Non-blocking UDP driven by its own mio event loop.
+pub struct MioUdp {
+    poll: Poll,
+    events: Events,
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    /// Datagrams per sendmmsg/recvmmsg, 1 for plain send_to/recv_from.
+    batch: usize,
+    /// Send with UDP_SEGMENT, until the kernel refuses.
+    gso: bool,
+    /// Set when receiving with UDP_GRO.
+    gro: Option<GroReader>,
+}
+
+pub struct MioUdpListener {
+    socket: Option<UdpSocket>,
+    local_addr: SocketAddr,
+    batch: usize,
+    gso: bool,
+}
+
+impl MioUdp {
+    fn new(
+        mut socket: UdpSocket,
+        peer: Option<SocketAddr>,
+        batch: usize,
+        offload: bool,
+    ) -> io::Result<Self> {
+        let fd = socket.as_raw_fd();
+        let gro = (offload && gso::enable_gro(fd)).then(GroReader::new);
+        let gso = offload && gso::gso_supported(fd);
+        if offload && (gro.is_none() || !gso) {
+            warn!("segmentation offload unsupported, one datagram per syscall");
+        }
+        let poll = Poll::new()?;
+        poll.registry().register(
+            &mut socket,
+            UDP_SOCKET,
+            Interest::READABLE.add(Interest::WRITABLE),
+        )?;
+        Ok(Self {
+            poll,
+            // Only a single socket is registered.
+            events: Events::with_capacity(1),
+            socket,
+            peer,
+            batch,
+            gso,
+            gro,
+        })
+    }
+
+    /// One datagram, from those GRO coalesced when receiving with it.
+    fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        match &mut self.gro {
+            Some(gro) => gro.recv_from(self.socket.as_raw_fd(), buf),
+            None => self.socket.recv_from(buf),
+        }
+    }
+
+    /// Fill `batch` with what GRO coalesced, `WouldBlock` if nothing is
+    /// queued.
+    fn recv_coalesced(&mut self, batch: &mut Batch) -> io::Result<()> {
+        while !batch.is_full() {
+            match self.recv_from(batch.spare()) {
+                Ok((n, from)) => batch.filled(n, Some(from)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock && !batch.is_empty() => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Block until the socket reports new readiness, or `timeout` passed.
+    fn wait(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        loop {
+            match self.poll.poll(&mut self.events, timeout) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => return res,
+            }
+        }
+    }
+}
+
+impl Transport for MioUdp {
+    type Listener = MioUdpListener;
+
+    const DELIVERY: Delivery = Delivery::Datagram;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<MioUdpListener> {
+        let socket = super::bind(addr, opt)?;
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from_std(socket);
+        Ok(MioUdpListener {
+            local_addr: socket.local_addr()?,
+            socket: Some(socket),
+            batch: opt.batch.into(),
+            gso: opt.gso,
+        })
+    }
+
+    async fn connect(addr: SocketAddr, opt: &TransportOpt) -> io::Result<Self> {
+        let socket = UdpSocket::bind(unspecified_for(&addr))?;
+        Self::new(socket, Some(addr), opt.batch.into(), opt.gso)
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        let peer = self.peer.ok_or_else(no_peer)?;
+        loop {
+            match self.socket.send_to(buf, peer) {
+                Ok(_) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.wait(None)?,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            // Readiness is edge triggered, only poll again once the queue is drained.
+            match self.recv_from(buf) {
+                Ok((n, from)) => {
+                    self.peer = Some(from);
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.wait(None)?,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn recv_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> io::Result<Option<usize>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.recv_from(buf) {
+                Ok((n, from)) => {
+                    self.peer = Some(from);
+                    return Ok(Some(n));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Ok(None);
+                    }
+                    self.wait(Some(left))?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn send_batch(&mut self, batch: &Batch) -> io::Result<()> {
+        let fd = self.socket.as_raw_fd();
+        // Echoes go back where they came from, a batch may hold several
+        // clients' datagrams.
+        let pkts = batch
+            .iter()
+            .enumerate()
+            .map(|(i, buf)| Ok((buf, batch.source(i).or(self.peer).ok_or_else(no_peer)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut sent = 0;
+        while sent < pkts.len() {
+            let rest = &pkts[sent..];
+            let (first, dst) = rest[0];
+            let res = if self.gso {
+                let run: Vec<&[u8]> = rest
+                    .iter()
+                    .take_while(|(_, to)| *to == dst)
+                    .map(|(buf, _)| *buf)
+                    .collect();
+                gso::send_segments(fd, &run, dst)
+            } else if self.batch > 1 {
+                batch::send_mmsg(fd, rest.iter().take(self.batch).copied())
+            } else {
+                self.socket.send_to(first, dst).map(|_| 1)
+            };
+            match res {
+                Ok(n) => sent += n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.wait(None)?,
+                Err(e) if self.gso && gso::refused(&e) => {
+                    warn!("UDP_SEGMENT refused, one datagram per syscall: {}", e);
+                    self.gso = false;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    async fn recv_batch(&mut self, batch: &mut Batch, timeout: Option<Duration>) -> io::Result<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            batch.clear();
+            let res = match self.batch {
+                _ if self.gro.is_some() => self.recv_coalesced(batch),
+                1 => self
+                    .socket
+                    .recv_from(batch.spare())
+                    .map(|(n, from)| batch.filled(n, Some(from))),
+                max => batch::recv_mmsg(self.socket.as_raw_fd(), batch, max),
+            };
+            match res {
+                Ok(()) => {
+                    self.peer = batch.source(batch.len() - 1).or(self.peer);
+                    return Ok(());
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+            let left = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            if left.is_some_and(|left| left.is_zero()) {
+                batch.clear();
+                return Ok(());
+            }
+            self.wait(left)?;
+        }
+    }
+}
+
+impl Listener for MioUdpListener {
+    type Conn = MioUdp;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    async fn accept(&mut self) -> io::Result<MioUdp> {
+        match self.socket.take() {
+            Some(socket) => MioUdp::new(socket, None, self.batch, self.gso),
+            None => loop {
+                std::thread::park();
+            },
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Raw UDP echo, one socket per side.
+//!
+//! A listener has no notion of connections, so it hands out one session that
+//! answers whichever peer sent the last datagram. With `--threads` every
+//! thread binds a session of its own to the same port, and the kernel spreads
+//! the clients' flows over them.
+
+mod async_std;
+mod mio;
+mod monoio;
+mod native;
+mod tokio;
+
+pub use self::async_std::{AsyncStdUdp, AsyncStdUdpListener};
+pub use self::mio::{MioUdp, MioUdpListener};
+pub use self::monoio::{MonoioUdp, MonoioUdpListener};
+pub use self::native::{NativeUdp, NativeUdpListener};
+pub use self::tokio::{TokioUdp, TokioUdpListener};
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+use crate::TransportOpt;
+
+/// Bind a server socket to `addr`, with SO_REUSEPORT when several threads
+/// serve the same port.
+pub(crate) fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<UdpSocket> {
+    match opt.threads {
+        1 => UdpSocket::bind(addr),
+        _ => bind_reuse_port(addr),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn bind_reuse_port(addr: SocketAddr) -> io::Result<UdpSocket> {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    let domain = match addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    // SAFETY: plain syscall, the descriptor is owned right after.
+    let fd = unsafe { libc::socket(domain, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fd` is a fresh descriptor nothing else owns.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let on: libc::c_int = 1;
+    let (addr, addr_len) = crate::batch::sockaddr(&addr);
+    // SAFETY: `on` and `addr` are valid for the lengths given.
+    let res = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_REUSEPORT,
+            &on as *const _ as *const _,
+            std::mem::size_of::<libc::c_int>() as _,
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: as above.
+    let res = unsafe { libc::bind(fd.as_raw_fd(), &addr as *const _ as *const _, addr_len) };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(UdpSocket::from(fd))
+}
+
+/// Only Linux spreads flows over the sockets sharing a port.
+#[cfg(not(target_os = "linux"))]
+fn bind_reuse_port(_addr: SocketAddr) -> io::Result<UdpSocket> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--threads needs SO_REUSEPORT load balancing, only on Linux",
+    ))
+}
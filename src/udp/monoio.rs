@@ -0,0 +1,108 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use monoio::net::udp::UdpSocket;
+
+use crate::transport::{no_peer, unspecified_for};
+use crate::{Delivery, Listener, Transport, TransportOpt};
+
+/// UDP on monoio, io_uring needs owned buffers so payloads go through `buf`.
+///
+/// A receive cancelled by its timeout takes the buffer with it, a new one is
+/// allocated in its place.
+pub struct MonoioUdp {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    buf: Option<Vec<u8>>,
+}
+
+pub struct MonoioUdpListener {
+    socket: Option<UdpSocket>,
+    local_addr: SocketAddr,
+}
+
+impl MonoioUdp {
+    fn new(socket: UdpSocket, peer: Option<SocketAddr>) -> Self {
+        Self {
+            socket,
+            peer,
+            buf: Some(Vec::new()),
+        }
+    }
+}
+
+impl Transport for MonoioUdp {
+    type Listener = MonoioUdpListener;
+
+    const DELIVERY: Delivery = Delivery::Datagram;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<MonoioUdpListener> {
+        let socket = super::bind(addr, opt)?;
+        // Needed by the legacy driver, harmless with io_uring.
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from_std(socket)?;
+        Ok(MonoioUdpListener {
+            local_addr: socket.local_addr()?,
+            socket: Some(socket),
+        })
+    }
+
+    async fn connect(addr: SocketAddr, _opt: &TransportOpt) -> io::Result<Self> {
+        let socket = UdpSocket::bind(unspecified_for(&addr))?;
+        Ok(Self::new(socket, Some(addr)))
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        let peer = self.peer.ok_or_else(no_peer)?;
+        let mut owned = self.buf.take().unwrap_or_default();
+        owned.clear();
+        owned.extend_from_slice(buf);
+        let (res, owned) = self.socket.send_to(owned, peer).await;
+        self.buf.replace(owned);
+        res?;
+        Ok(())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut owned = self.buf.take().unwrap_or_default();
+        owned.clear();
+        owned.reserve(buf.len());
+        let (res, owned) = self.socket.recv_from(owned).await;
+        let n = res.map(|(n, from)| {
+            self.peer = Some(from);
+            n.min(buf.len())
+        });
+        if let Ok(n) = n {
+            buf[..n].copy_from_slice(&owned[..n]);
+        }
+        self.buf.replace(owned);
+        n
+    }
+
+    async fn recv_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> io::Result<Option<usize>> {
+        match monoio::time::timeout(timeout, self.recv(buf)).await {
+            Ok(res) => res.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl Listener for MonoioUdpListener {
+    type Conn = MonoioUdp;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    async fn accept(&mut self) -> io::Result<MonoioUdp> {
+        match self.socket.take() {
+            Some(socket) => Ok(MonoioUdp::new(socket, None)),
+            None => std::future::pending().await,
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use crate::transport::{no_peer, unspecified_for};
+use crate::{Delivery, Listener, Transport, TransportOpt};
+
+/// Blocking UDP from the standard library.
+pub struct NativeUdp {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    /// What the socket's read timeout is currently set to.
+    read_timeout: Option<Duration>,
+}
+
+pub struct NativeUdpListener {
+    socket: Option<UdpSocket>,
+    local_addr: SocketAddr,
+}
+
+impl NativeUdp {
+    fn new(socket: UdpSocket, peer: Option<SocketAddr>) -> Self {
+        Self {
+            socket,
+            peer,
+            read_timeout: None,
+        }
+    }
+
+    /// Change the read timeout, a syscall only when it differs.
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        if self.read_timeout != timeout {
+            self.socket.set_read_timeout(timeout)?;
+            self.read_timeout = timeout;
+        }
+        Ok(())
+    }
+}
+
+impl Transport for NativeUdp {
+    type Listener = NativeUdpListener;
+
+    const DELIVERY: Delivery = Delivery::Datagram;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<NativeUdpListener> {
+        let socket = super::bind(addr, opt)?;
+        Ok(NativeUdpListener {
+            local_addr: socket.local_addr()?,
+            socket: Some(socket),
+        })
+    }
+
+    async fn connect(addr: SocketAddr, _opt: &TransportOpt) -> io::Result<Self> {
+        let socket = UdpSocket::bind(unspecified_for(&addr))?;
+        Ok(Self::new(socket, Some(addr)))
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        let peer = self.peer.ok_or_else(no_peer)?;
+        self.socket.send_to(buf, peer)?;
+        Ok(())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.set_read_timeout(None)?;
+        let (n, from) = self.socket.recv_from(buf)?;
+        self.peer = Some(from);
+        Ok(n)
+    }
+
+    async fn recv_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> io::Result<Option<usize>> {
+        // A zero timeout would be rejected by the socket.
+        self.set_read_timeout(Some(timeout.max(Duration::from_micros(1))))?;
+        match self.socket.recv_from(buf) {
+            Ok((n, from)) => {
+                self.peer = Some(from);
+                Ok(Some(n))
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Listener for NativeUdpListener {
+    type Conn = NativeUdp;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    async fn accept(&mut self) -> io::Result<NativeUdp> {
+        match self.socket.take() {
+            Some(socket) => Ok(NativeUdp::new(socket, None)),
+            None => loop {
+                std::thread::park();
+            },
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use crate::transport::{no_peer, unspecified_for};
+use crate::{Delivery, Listener, Transport, TransportOpt};
+
+/// UDP on the tokio reactor.
+pub struct TokioUdp {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+}
+
+pub struct TokioUdpListener {
+    socket: Option<UdpSocket>,
+    local_addr: SocketAddr,
+}
+
+impl Transport for TokioUdp {
+    type Listener = TokioUdpListener;
+
+    const DELIVERY: Delivery = Delivery::Datagram;
+
+    async fn bind(addr: SocketAddr, opt: &TransportOpt) -> io::Result<TokioUdpListener> {
+        let socket = super::bind(addr, opt)?;
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from_std(socket)?;
+        Ok(TokioUdpListener {
+            local_addr: socket.local_addr()?,
+            socket: Some(socket),
+        })
+    }
+
+    async fn connect(addr: SocketAddr, _opt: &TransportOpt) -> io::Result<Self> {
+        let socket = UdpSocket::bind(unspecified_for(&addr)).await?;
+        Ok(Self {
+            socket,
+            peer: Some(addr),
+        })
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        let peer = self.peer.ok_or_else(no_peer)?;
+        self.socket.send_to(buf, peer).await?;
+        Ok(())
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (n, from) = self.socket.recv_from(buf).await?;
+        self.peer = Some(from);
+        Ok(n)
+    }
+
+    async fn recv_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> io::Result<Option<usize>> {
+        match tokio::time::timeout(timeout, self.recv(buf)).await {
+            Ok(res) => res.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl Listener for TokioUdpListener {
+    type Conn = TokioUdp;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    async fn accept(&mut self) -> io::Result<TokioUdp> {
+        match self.socket.take() {
+            Some(socket) => Ok(TokioUdp { socket, peer: None }),
+            None => std::future::pending().await,
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Payloads that can be checked after their round trip: a sequence number
+//! followed, when verifying, by a pattern derived from it.
+
+use crate::report::Integrity;
+
+/// Bytes taken by the sequence number.
+pub const SEQ_LEN: usize = 8;
+
+/// What an echo turned out to be, anything but `Ok` is counted into
+/// [`Integrity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Ok,
+    /// Bytes differ from what was sent, or the message was truncated.
+    Corrupt,
+    /// Arrived after a message that was sent later.
+    Reordered,
+    /// Was already echoed before.
+    Duplicated,
+    /// Arrived after it had been given up as lost.
+    Late,
+}
+
+impl Check {
+    pub fn count(self, integrity: &mut Integrity) {
+        match self {
+            Check::Ok => {}
+            Check::Corrupt => integrity.corrupt += 1,
+            Check::Reordered => integrity.reordered += 1,
+            Check::Duplicated => integrity.duplicated += 1,
+            Check::Late => integrity.late += 1,
+        }
+    }
+}
+
+/// Writes and reads back the sequence numbered messages of one client.
+pub struct Payload {
+    len: usize,
+    pattern: bool,
+    expected: Vec<u8>,
+}
+
+impl Payload {
+    /// Messages of `len` bytes, followed by the pattern when `pattern` is
+    /// set. `len` must fit the sequence number.
+    pub fn new(len: usize, pattern: bool) -> Self {
+        assert!(len >= SEQ_LEN, "payload too short for a sequence number");
+        Self {
+            len,
+            pattern,
+            expected: Vec::new(),
+        }
+    }
+
+    /// Fill `buf` with `seq` and its pattern.
+    pub fn write(&self, buf: &mut [u8], seq: u64) {
+        buf[..SEQ_LEN].copy_from_slice(&seq.to_le_bytes());
+        if self.pattern {
+            pattern(seq, &mut buf[SEQ_LEN..]);
+        }
+    }
+
+    /// Sequence number of an echo, `None` if it is not what was written.
+    pub fn read(&mut self, buf: &[u8]) -> Option<u64> {
+        if buf.len() != self.len {
+            return None;
+        }
+        let seq = u64::from_le_bytes(buf[..SEQ_LEN].try_into().unwrap());
+        if self.pattern {
+            self.expected.resize(self.len - SEQ_LEN, 0);
+            pattern(seq, &mut self.expected);
+            if buf[SEQ_LEN..] != self.expected[..] {
+                return None;
+            }
+        }
+        Some(seq)
+    }
+
+    /// Check an echo from a stream, where it has to be `expected`.
+    pub fn check_in_order(&mut self, buf: &[u8], expected: u64) -> Check {
+        match self.read(buf) {
+            None => Check::Corrupt,
+            Some(seq) if seq == expected => Check::Ok,
+            Some(seq) if seq < expected => Check::Duplicated,
+            Some(_) => Check::Reordered,
+        }
+    }
+}
+
+/// Deterministic bytes for `seq`, xorshift seeded by the sequence number.
+fn pattern(seq: u64, buf: &mut [u8]) {
+    let mut state = seq ^ 0x9e37_79b9_7f4a_7c15;
+    for chunk in buf.chunks_mut(8) {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        chunk.copy_from_slice(&state.to_le_bytes()[..chunk.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_stream_echoes() {
+        let mut payload = Payload::new(64, true);
+        let mut sent = vec![vec![0; 64]; 3];
+        for (seq, buf) in sent.iter_mut().enumerate() {
+            payload.write(buf, seq as u64);
+        }
+
+        assert_eq!(payload.check_in_order(&sent[0], 0), Check::Ok);
+        assert_eq!(payload.check_in_order(&sent[2], 1), Check::Reordered);
+        assert_eq!(payload.check_in_order(&sent[0], 2), Check::Duplicated);
+        assert_eq!(payload.check_in_order(&sent[1][..40], 1), Check::Corrupt);
+        sent[1][40] ^= 1;
+        assert_eq!(payload.check_in_order(&sent[1], 1), Check::Corrupt);
+    }
+}
@@ -0,0 +1,214 @@
+//! Bookkeeping of the datagrams a client has in flight.
+//!
+//! Datagrams that are not echoed within the loss timeout are given up and
+//! replaced by new ones, so a lossy path cannot shrink the window until the
+//! benchmark stalls. Nothing is retransmitted, every datagram carries a fresh
+//! sequence number.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::verify::Check;
+
+/// Loss timeout before the first round trip has been measured.
+const INITIAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Bounds of the adaptive loss timeout. The lower one is far below RFC
+/// 6298's second, loopback round trips are counted in microseconds.
+const MIN_TIMEOUT: Duration = Duration::from_millis(10);
+const MAX_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Lost datagrams remembered to tell late echoes from duplicates.
+const LOST_HISTORY: usize = 1 << 16;
+
+pub struct Window {
+    next_seq: u64,
+    /// Send time of every datagram in flight, oldest first.
+    sent: BTreeMap<u64, Instant>,
+    /// Highest sequence number echoed so far.
+    highest: Option<u64>,
+    /// Send time of datagrams given up as lost.
+    lost: BTreeMap<u64, Instant>,
+    fixed_timeout: Option<Duration>,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    /// Doubles with every expiry until the next round trip is measured.
+    backoff: u32,
+}
+
+impl Window {
+    /// A window whose loss timeout follows the measured round trips, as a
+    /// TCP retransmission timeout would, unless `fixed_timeout` is given.
+    pub fn new(fixed_timeout: Option<Duration>) -> Self {
+        Self {
+            next_seq: 0,
+            sent: BTreeMap::new(),
+            highest: None,
+            lost: BTreeMap::new(),
+            fixed_timeout,
+            srtt: None,
+            rttvar: Duration::ZERO,
+            backoff: 1,
+        }
+    }
+
+    /// Datagrams in flight.
+    pub fn len(&self) -> usize {
+        self.sent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sent.is_empty()
+    }
+
+    /// Sequence number for the next datagram, sent now.
+    pub fn send(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.sent.insert(seq, Instant::now());
+        seq
+    }
+
+    /// Classify the echo of `seq`, with its round trip if it was in flight.
+    pub fn echoed(&mut self, seq: u64) -> (Check, Option<Duration>) {
+        if let Some(sent_at) = self.sent.remove(&seq) {
+            let rtt = sent_at.elapsed();
+            self.sample(rtt);
+            let check = match self.highest {
+                Some(highest) if highest > seq => Check::Reordered,
+                _ => {
+                    self.highest = Some(seq);
+                    Check::Ok
+                }
+            };
+            return (check, Some(rtt));
+        }
+        if let Some(sent_at) = self.lost.remove(&seq) {
+            // Sequence numbers are never reused, so even a late echo is a
+            // valid round trip, and the one telling the timeout to grow.
+            self.sample(sent_at.elapsed());
+            return (Check::Late, None);
+        }
+        let check = match seq < self.next_seq {
+            true => Check::Duplicated,
+            false => Check::Corrupt,
+        };
+        (check, None)
+    }
+
+    /// Give up on datagrams in flight for longer than the loss timeout,
+    /// returning how many.
+    pub fn expire(&mut self, now: Instant) -> u64 {
+        let timeout = self.timeout();
+        let mut lost = 0;
+        while let Some(entry) = self.sent.first_entry() {
+            if now.saturating_duration_since(*entry.get()) < timeout {
+                break;
+            }
+            let (seq, sent_at) = entry.remove_entry();
+            self.lost.insert(seq, sent_at);
+            lost += 1;
+        }
+        if lost > 0 {
+            self.backoff = (self.backoff * 2).min(64);
+        }
+        while self.lost.len() > LOST_HISTORY {
+            self.lost.pop_first();
+        }
+        lost
+    }
+
+    /// Current loss timeout, in whole milliseconds.
+    pub fn timeout(&self) -> Duration {
+        if let Some(timeout) = self.fixed_timeout {
+            return timeout;
+        }
+        let timeout = match self.srtt {
+            // Steady delays leave almost no variation, the floor keeps a
+            // slightly slower round trip from counting as a loss.
+            Some(srtt) => srtt + (4 * self.rttvar).max(MIN_TIMEOUT),
+            None => INITIAL_TIMEOUT,
+        } * self.backoff;
+        // Rounded so transports setting it on the socket rarely have to.
+        let ms = timeout.as_micros().div_ceil(1000) as u64;
+        Duration::from_millis(ms).clamp(MIN_TIMEOUT, MAX_TIMEOUT)
+    }
+
+    /// Smoothed round trip and its variation, per RFC 6298.
+    fn sample(&mut self, rtt: Duration) {
+        self.backoff = 1;
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                self.rttvar = (3 * self.rttvar + srtt.abs_diff(rtt)) / 4;
+                self.srtt = Some((7 * srtt + rtt) / 8);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn smooths_round_trips() {
+        let mut window = Window::new(None);
+        assert_eq!(window.timeout(), INITIAL_TIMEOUT);
+        window.sample(100 * MS);
+        assert_eq!((window.srtt, window.rttvar), (Some(100 * MS), 50 * MS));
+        assert_eq!(window.timeout(), 300 * MS);
+        window.sample(20 * MS);
+        assert_eq!(
+            (window.srtt, window.rttvar),
+            (Some(90 * MS), 57_500 * MS / 1000)
+        );
+        assert_eq!(window.timeout(), 320 * MS);
+
+        // Steady round trips leave the floor on top of them.
+        let mut window = Window::new(None);
+        window.sample(MS);
+        assert_eq!(window.timeout(), 11 * MS);
+        assert_eq!(Window::new(Some(5 * MS)).timeout(), 5 * MS);
+    }
+
+    #[test]
+    fn expires_and_backs_off_until_an_echo() {
+        let mut window = Window::new(None);
+        window.sample(100 * MS);
+        let first = window.send();
+        window.send();
+        let start = Instant::now();
+        assert_eq!(window.expire(start), 0);
+        assert_eq!(window.expire(start + 300 * MS), 2);
+        assert!(window.is_empty());
+        assert_eq!(window.timeout(), 600 * MS);
+
+        // Nothing expired, nothing doubles.
+        assert_eq!(window.expire(start + 300 * MS), 0);
+        assert_eq!(window.timeout(), 600 * MS);
+        window.send();
+        assert_eq!(window.expire(Instant::now() + 600 * MS), 1);
+        assert_eq!(window.timeout(), MAX_TIMEOUT);
+
+        // A late echo is a round trip, which resets the backoff.
+        assert_eq!(window.echoed(first), (Check::Late, None));
+        assert_eq!(window.backoff, 1);
+        assert!(window.timeout() < 600 * MS);
+    }
+
+    #[test]
+    fn classifies_echoes() {
+        let mut window = Window::new(None);
+        let (a, b) = (window.send(), window.send());
+        assert!(matches!(window.echoed(b), (Check::Ok, Some(_))));
+        assert!(matches!(window.echoed(a), (Check::Reordered, Some(_))));
+        assert_eq!(window.echoed(a), (Check::Duplicated, None));
+        assert_eq!(window.echoed(b + 1), (Check::Corrupt, None));
+    }
+}